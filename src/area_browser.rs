@@ -0,0 +1,245 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use bevy_panorbit_camera::PanOrbitCamera;
+use parquet::file::{reader::FileReader, serialized_reader::SerializedFileReader};
+use std::path::Path;
+
+use crate::building::Building;
+use crate::geo_frame::GeoFrame;
+use crate::loading::{AppState, MapLoadParams};
+use crate::map_config::{parse_area_name, Layer, MapConfig};
+use crate::projection::Projection;
+use crate::regions::CacheRecord;
+use crate::road_graph::RoadGraph;
+use crate::routing::RouteState;
+use crate::tiles::{update_tiles, MapTiles, TileCoord};
+use crate::transportation::RoadSegment;
+
+/// The area on screen.
+#[derive(Resource)]
+pub struct CurrentArea {
+    pub config: MapConfig,
+    /// Layers asked for at launch, before dropping the uncached ones.
+    pub layers: Vec<Layer>,
+}
+
+/// An area with at least one `{lon}_{lat}_{name}_{suffix}.parquet` file.
+#[derive(Debug, Clone)]
+pub struct CachedArea {
+    pub lon: f64,
+    pub lat: f64,
+    pub name: String,
+    /// Cached layers with their feature count, `None` if unreadable.
+    pub layers: Vec<(Layer, Option<u64>)>,
+}
+
+impl CachedArea {
+    pub fn area_name(&self) -> String {
+        format!("{}_{}_{}", self.lon, self.lat, self.name)
+    }
+}
+
+/// Splits a cached file name into lon, lat, name and layer.
+fn parse_file_name(file_name: &str) -> Option<(f64, f64, &str, Layer)> {
+    let stem = file_name.strip_suffix(".parquet")?;
+    let (area, layer) = Layer::ALL.iter().find_map(|layer| {
+        stem.strip_suffix(layer.file_suffix())
+            .and_then(|s| s.strip_suffix('_'))
+            .map(|area| (area, *layer))
+    })?;
+    let (lon, lat, name) = parse_area_name(area)?;
+    Some((lon, lat, name, layer))
+}
+
+fn parquet_rows(path: &Path) -> Option<u64> {
+    let file = std::fs::File::open(path).ok()?;
+    let reader = SerializedFileReader::new(file).ok()?;
+    Some(reader.metadata().file_metadata().num_rows() as u64)
+}
+
+/// Areas cached in `config.parquet_dir`, sorted by name. Feature counts come
+/// from the `cli cache-all` record if there is one, else the parquet footers.
+pub fn scan_areas(config: &MapConfig) -> std::io::Result<Vec<CachedArea>> {
+    let mut areas: Vec<CachedArea> = vec![];
+    for entry in std::fs::read_dir(&config.parquet_dir)? {
+        let file_name = entry?.file_name();
+        let Some((lon, lat, name, layer)) = file_name.to_str().and_then(parse_file_name) else {
+            continue;
+        };
+        let index = match areas
+            .iter()
+            .position(|a| a.lon == lon && a.lat == lat && a.name == name)
+        {
+            Some(index) => index,
+            None => {
+                areas.push(CachedArea {
+                    lon,
+                    lat,
+                    name: name.to_string(),
+                    layers: vec![],
+                });
+                areas.len() - 1
+            }
+        };
+        areas[index].layers.push((layer, None));
+    }
+
+    for area in areas.iter_mut() {
+        let area_config = MapConfig {
+            lon: area.lon,
+            lat: area.lat,
+            name: area.name.clone(),
+            ..config.clone()
+        };
+        let record = CacheRecord::load(&area_config.record_path()).ok();
+        for (layer, rows) in area.layers.iter_mut() {
+            *rows = record
+                .as_ref()
+                .and_then(|r| r.layers.iter().find(|l| l.layer == *layer))
+                .map(|l| l.rows)
+                .or_else(|| parquet_rows(&area_config.parquet_path(*layer)));
+        }
+        area.layers.sort_by_key(|(layer, _)| *layer as u8);
+    }
+    areas.sort_by(|a, b| a.name.cmp(&b.name).then(a.lon.total_cmp(&b.lon)));
+    Ok(areas)
+}
+
+#[derive(Resource, Default)]
+pub struct AreaBrowser {
+    /// `None` until first shown or after "Refresh".
+    areas: Option<Vec<CachedArea>>,
+    /// Picked in the list, loaded by `switch_area`.
+    selected: Option<CachedArea>,
+    error: Option<String>,
+}
+
+pub fn area_browser_ui(
+    mut egui: EguiContexts,
+    current: Res<CurrentArea>,
+    mut browser: ResMut<AreaBrowser>,
+) {
+    let browser = &mut *browser;
+    if browser.areas.is_none() {
+        match scan_areas(&current.config) {
+            Ok(areas) => browser.areas = Some(areas),
+            Err(e) => {
+                browser.areas = Some(vec![]);
+                browser.error = Some(format!("{}: {e}", current.config.parquet_dir.display()));
+            }
+        }
+    }
+
+    let current_name = current.config.area_name();
+    egui::Window::new("Areas")
+        .anchor(egui::Align2::LEFT_BOTTOM, [8., -32.])
+        .default_open(false)
+        .show(egui.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label(current.config.parquet_dir.display().to_string());
+                if ui.small_button("Refresh").clicked() {
+                    browser.areas = None;
+                    browser.error = None;
+                }
+            });
+            egui::ScrollArea::vertical()
+                .max_height(320.)
+                .show(ui, |ui| {
+                    for area in browser.areas.iter().flatten() {
+                        let is_current = area.area_name() == current_name;
+                        ui.separator();
+                        ui.horizontal(|ui| {
+                            ui.strong(&area.name);
+                            ui.weak(format!("{:.4}, {:.4}", area.lon, area.lat));
+                            if is_current {
+                                ui.label("(shown)");
+                            } else if ui.button("Load").clicked() {
+                                browser.selected = Some(area.clone());
+                            }
+                        });
+                        for (layer, rows) in area.layers.iter() {
+                            let rows = rows.map_or("?".to_string(), |r| r.to_string());
+                            ui.label(format!("{}: {rows}", layer.file_suffix()));
+                        }
+                    }
+                });
+            if let Some(error) = &browser.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        });
+}
+
+/// Replaces the shown area with the one picked in the browser: despawns the
+/// loaded features, moves the frame origin to the new area and lets the
+/// tiles load again around it.
+#[allow(clippy::too_many_arguments)]
+pub fn switch_area(
+    mut cmd: Commands,
+    mut browser: ResMut<AreaBrowser>,
+    mut current: ResMut<CurrentArea>,
+    mut frame: ResMut<GeoFrame>,
+    mut params: ResMut<MapLoadParams>,
+    mut map_tiles: ResMut<MapTiles>,
+    mut graph: ResMut<RoadGraph>,
+    mut route: ResMut<RouteState>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut cameras: Query<&mut PanOrbitCamera>,
+    features: Query<Entity, Or<(With<TileCoord>, With<Building>, With<RoadSegment>)>>,
+) {
+    let Some(area) = browser.selected.take() else {
+        return;
+    };
+    let mut config = MapConfig {
+        lon: area.lon,
+        lat: area.lat,
+        name: area.name.clone(),
+        layers: current.layers.clone(),
+        region: None,
+        ..current.config.clone()
+    };
+    match config.retain_cached_layers() {
+        Ok(missing) => {
+            for path in missing {
+                warn!("{} not found, layer disabled", path.display());
+            }
+        }
+        Err(e) => {
+            browser.error = Some(e.to_string());
+            return;
+        }
+    }
+    browser.error = None;
+    info!("switching to {}", config.area_name());
+
+    for entity in features.iter() {
+        cmd.entity(entity).despawn_recursive();
+    }
+    // Dropping the tiles also cancels their pending queries.
+    *map_tiles = MapTiles::default();
+    *graph = RoadGraph::default();
+    // Route points are in the old frame.
+    route.origin = None;
+    route.destination = None;
+    route.picking = false;
+    route.dirty = true;
+
+    let projection = Projection::new(config.projection, config.lon, config.lat);
+    *frame = GeoFrame::new(projection, config.lon, config.lat);
+    *params = MapLoadParams::new(&config, projection);
+    for mut camera in cameras.iter_mut() {
+        camera.focus = Vec3::ZERO;
+        camera.target_focus = Vec3::ZERO;
+    }
+    current.config = config;
+    next_state.set(AppState::Loading);
+}
+
+/// Needs a `CurrentArea` resource.
+pub struct AreaBrowserPlugin;
+
+impl Plugin for AreaBrowserPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AreaBrowser>()
+            .add_systems(Update, (area_browser_ui, switch_area.before(update_tiles)));
+    }
+}
@@ -2,15 +2,21 @@ use bevy::{prelude::*, render::mesh::*};
 use bevy_mod_picking::events::{Click, Pointer};
 use bevy_mod_picking::prelude::On;
 use geo::algorithm::TriangulateEarcut;
-use geo_types::Polygon;
+use geo_types::{LineString, Polygon};
 use serde::{Deserialize, Serialize};
 use std::f32::consts::FRAC_PI_2;
 use std::ops::Sub;
 use std::str::FromStr;
 use strum_macros::EnumIter;
 
-use crate::material::MapMaterialHandle;
-use crate::KxyGeodesic;
+use crate::footprint::QaFlag;
+use crate::height::HeightSource;
+use crate::material::{parse_color, ColorMaterials, MapMaterialHandle};
+use crate::overture::FeatureMeta;
+use crate::projection::Projection;
+use crate::roof::Roof;
+use crate::selection::FeatureClicked;
+use crate::tiles::MapTiles;
 
 // https://docs.overturemaps.org/reference/buildings/building
 #[derive(Default, EnumIter, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -55,17 +61,51 @@ impl FromStr for BuildingClass {
     }
 }
 
+pub const FLOOR_HEIGHT: f32 = 3.;
+
 #[derive(Component, Debug, Clone)]
 pub struct Building {
+    pub meta: FeatureMeta,
     pub class: Option<BuildingClass>,
     pub names: Option<Names>,
     pub translate: [f64; 2],
     pub height: Option<f64>,
     pub num_floors: Option<i32>,
     pub line: Vec<[f64; 2]>,
-    pub k: KxyGeodesic,
+    /// Interior rings, wound against `line`.
+    pub holes: Vec<Vec<[f64; 2]>>,
     pub vertices: Vec<[f64; 3]>,
     pub triangle_indices: Vec<u32>,
+    /// Footprint repairs, see `repair_footprint`.
+    pub qa: Vec<QaFlag>,
+    pub attrs: BuildingAttrs,
+    /// Set with `estimated_height` and `floor_height` by `HeightEstimator`.
+    pub height_source: HeightSource,
+    /// Used when `height` is missing.
+    pub estimated_height: Option<f32>,
+    /// Storey height for `min_floor`.
+    pub floor_height: f32,
+}
+
+/// Columns shared by `building` and `building_part`, beyond the footprint
+/// and `height`/`numFloors`.
+#[derive(Debug, Clone, Default)]
+pub struct BuildingAttrs {
+    /// Height of the bottom of the walls, for parts above ground.
+    pub min_height: Option<f64>,
+    pub min_floor: Option<i32>,
+    /// `flat`, `gabled`, `hipped`, ...
+    pub roof_shape: Option<String>,
+    pub roof_height: Option<f64>,
+    /// Bearing of the roof ridge, degrees clockwise from north.
+    pub roof_direction: Option<f64>,
+    /// Name or `#rrggbb`.
+    pub facade_color: Option<String>,
+    pub facade_material: Option<String>,
+    pub roof_color: Option<String>,
+    pub roof_material: Option<String>,
+    /// Building a `building_part` belongs to.
+    pub building_id: Option<String>,
 }
 
 impl Building {
@@ -73,19 +113,44 @@ impl Building {
         props: BuildingGeometryProps,
         class: Option<BuildingClass>,
         names: Option<Names>,
+        meta: FeatureMeta,
     ) -> Self {
         Building {
+            meta,
             class,
             names,
             translate: props.translate,
             height: props.height,
             num_floors: props.num_floors,
             line: props.line,
-            k: props.k,
+            holes: props.holes,
             vertices: props.vertices,
             triangle_indices: props.triangle_indices,
+            qa: vec![],
+            attrs: BuildingAttrs::default(),
+            height_source: HeightSource::default(),
+            estimated_height: None,
+            floor_height: FLOOR_HEIGHT,
         }
     }
+
+    /// Bottom and top of the walls in metres, see `HeightEstimator` for
+    /// buildings without `height`; a single storey if it hasn't run.
+    pub fn extrusion(&self) -> (f32, f32) {
+        let min_height: f32 = match self.attrs.min_height {
+            Some(h) => h as f32,
+            None => self
+                .attrs
+                .min_floor
+                .map_or(0., |f| f as f32 * self.floor_height),
+        };
+        let height: f32 = match self.height {
+            Some(h) => h as f32,
+            None => self.estimated_height.unwrap_or(self.floor_height),
+        };
+        // Keep a sliver of wall for parts with bad heights.
+        (min_height, height.max(min_height + 0.1))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -122,16 +187,14 @@ pub struct BuildingGeometryProps {
     pub translate: [f64; 2],
     pub height: Option<f64>,
     pub num_floors: Option<i32>,
+    /// Exterior ring.
     pub line: Vec<[f64; 2]>,
-    pub k: KxyGeodesic,
+    /// Interior rings, wound against `line`.
+    pub holes: Vec<Vec<[f64; 2]>>,
     pub vertices: Vec<[f64; 3]>,
     pub triangle_indices: Vec<u32>,
 }
 
-#[derive(Resource, Debug)]
-pub struct Buildings {
-    pub buildings: Vec<Building>,
-}
 // pub fn polygon_base(polygon: &Polygon) -> (f64, [f64; 2]) {
 //     let exterior = polygon.exterior();
 //     let c1 = exterior
@@ -151,10 +214,11 @@ pub struct Buildings {
 //     (k, first_point_position)
 // }
 
+/// Expects the winding from `repair_footprint`: walls face away from the
+/// solid side, outwards on the exterior and into courtyards on holes.
 pub fn polygon_building(
     polygon: Polygon,
-    k: KxyGeodesic,
-    center: [f64; 2],
+    projection: Projection,
     height: Option<f64>,
     num_floors: Option<i32>,
 ) -> BuildingGeometryProps {
@@ -164,17 +228,18 @@ pub fn polygon_building(
         .next()
         .expect("To take exterior:0 coordinate");
 
-    let translate: [f64; 2] = [c1.x * k[0] - center[0], -c1.y * k[1] - center[1]]; // Yto-Z
+    let translate: [f64; 2] = projection.forward(c1.x, c1.y);
 
-    let line: Vec<[f64; 2]> = exterior
-        .coords()
-        .map(|c| {
-            [
-                c.x * k[0] - center[0] - translate[0],
-                -c.y * k[1] - center[1] - translate[1], // Yto-Z
-            ]
-        })
-        .collect();
+    let ring_xz = |ring: &LineString| -> Vec<[f64; 2]> {
+        ring.coords()
+            .map(|c| {
+                let [x, z] = projection.forward(c.x, c.y);
+                [x - translate[0], z - translate[1]]
+            })
+            .collect()
+    };
+    let line: Vec<[f64; 2]> = ring_xz(exterior);
+    let holes: Vec<Vec<[f64; 2]>> = polygon.interiors().iter().map(ring_xz).collect();
 
     // println!("line l:{} :{:?}", line.len(), &line);
     // for (i, l) in line.iter().enumerate() {
@@ -200,16 +265,13 @@ pub fn polygon_building(
         height,
         num_floors,
         line,
-        k,
+        holes,
         vertices: triangles
             .vertices
             .chunks(2)
             .map(|i| {
-                [
-                    i[0] * k[0] - center[0] - translate[0],
-                    0.,
-                    -i[1] * k[1] - center[1] - translate[1], // Yto-Z
-                ]
+                let [x, z] = projection.forward(i[0], i[1]);
+                [x - translate[0], 0., z - translate[1]]
             })
             .collect(),
         triangle_indices: triangles
@@ -220,18 +282,6 @@ pub fn polygon_building(
     }
 }
 
-pub fn buildings_start(
-    mut cmd: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    map_materials: Res<MapMaterialHandle>,
-    buildings_res: Res<Buildings>,
-) {
-    for b in buildings_res.buildings.iter() {
-        spawn_building(&mut cmd, &mut meshes, &mut materials, b, &map_materials);
-    }
-}
-
 impl From<&BuildingClass> for Color {
     fn from(building_class: &BuildingClass) -> Self {
         match building_class {
@@ -252,16 +302,13 @@ impl From<&BuildingClass> for Color {
     }
 }
 
-pub fn _buildings_update(buildings_res: Res<Buildings>, mut gizmos: Gizmos) {
-    for b in buildings_res.buildings.iter() {
-        let height: f32 = match b.height {
-            Some(h) => h as f32,
-            None => 10.,
-        };
-        let wall = Wall::new(&b.line, height);
+pub fn _buildings_update(map_tiles: Res<MapTiles>, mut gizmos: Gizmos) {
+    for b in map_tiles.tiles.values().flat_map(|t| t.buildings.iter()) {
+        let (min_height, height) = b.extrusion();
+        let wall = Wall::with_holes(&b.line, &b.holes, &|_| height - min_height);
 
         for (i, n) in wall.normals.iter().enumerate() {
-            let tr = Vec3::new(b.translate[0] as f32, 0., b.translate[1] as f32);
+            let tr = Vec3::new(b.translate[0] as f32, min_height, b.translate[1] as f32);
             let n = Vec3::new(n[0], n[1], n[2]);
             let v = wall.vertices[i];
             let v = Vec3::new(v[0], v[1] + 0.01, v[2]);
@@ -273,19 +320,19 @@ pub fn _buildings_update(buildings_res: Res<Buildings>, mut gizmos: Gizmos) {
 pub fn spawn_building(
     cmd: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
-    _materials: &mut ResMut<Assets<StandardMaterial>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
     building: &Building,
     map_materials: &Res<MapMaterialHandle>,
-) {
-    let height: f32 = match building.height {
-        Some(h) => h as f32,
-        None => match building.num_floors {
-            Some(floors) => floors as f32 * 3.,
-            None => 10.,
-        },
-    };
-
-    let wall = Wall::new(&building.line, height);
+    color_materials: &mut ResMut<ColorMaterials>,
+) -> [Entity; 2] {
+    let (min_height, height) = building.extrusion();
+    let roof = Roof::new(building, height - min_height);
+    let eave = height - roof.height;
+
+    // Walls stop at the eave and follow the roof up into gables.
+    let line = roof.split_ring(&building.line);
+    let holes: Vec<Vec<[f64; 2]>> = building.holes.iter().map(|h| roof.split_ring(h)).collect();
+    let wall = Wall::with_holes(&line, &holes, &|p| eave - min_height + roof.height_at(p));
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
     mesh.insert_attribute(
         Mesh::ATTRIBUTE_POSITION,
@@ -303,64 +350,83 @@ pub fn spawn_building(
         0.,
         building.translate[1] as f32,
     );
-    let transform = Transform::from_translation(translate);
-    let handle: Handle<StandardMaterial> = match &building.class {
-        Some(c) => map_materials.walls.get(c).unwrap().clone(),
-        None => map_materials.unknown_building.clone(),
+    let transform = Transform::from_translation(translate + Vec3::Y * min_height);
+    // Colours from the data win over the class palette.
+    let facade_color = building.attrs.facade_color.as_deref().and_then(parse_color);
+    let handle: Handle<StandardMaterial> = match (facade_color, &building.class) {
+        (Some(color), _) => color_materials.get_or_add(color, materials),
+        (None, Some(c)) => map_materials.walls.get(c).unwrap().clone(),
+        (None, None) => map_materials.unknown_building.clone(),
     };
-    cmd.spawn((
-        PbrBundle {
-            mesh: meshes.add(mesh),
-            material: handle.clone(),
-            transform,
-            ..Default::default()
-        },
-        building.clone(),
-    ));
+    let walls = cmd
+        .spawn((
+            PbrBundle {
+                mesh: meshes.add(mesh),
+                material: handle.clone(),
+                transform,
+                ..Default::default()
+            },
+            building.clone(),
+            On::<Pointer<Click>>::send_event::<FeatureClicked>(),
+        ))
+        .id();
 
     // ROOF
+    let roof_mesh = roof.mesh(building);
     let mut roof = Mesh::new(PrimitiveTopology::TriangleList);
-    let vertices: Vec<[f32; 3]> = building
-        .vertices
-        .iter()
-        .map(|v| v.map(|p| p as f32))
-        .collect();
-    roof.insert_attribute(
-        Mesh::ATTRIBUTE_POSITION,
-        VertexAttributeValues::from(vertices.clone()),
-    );
-    roof.insert_attribute(
-        Mesh::ATTRIBUTE_NORMAL,
-        VertexAttributeValues::from(
+    let mut vertices: Vec<[f32; 3]> = roof_mesh.vertices;
+    let mut normals: Vec<[f32; 3]> = roof_mesh.normals;
+    let mut indices = roof_mesh.indices;
+    if min_height > 0. {
+        // Underside, seen from below parts that overhang or float.
+        let offset = vertices.len() as u32;
+        let bottom = min_height - eave;
+        vertices.extend(
             building
                 .vertices
                 .iter()
-                .map(|_| [0., 1., 0.] as [f32; 3])
-                .collect::<Vec<[f32; 3]>>(),
-        ),
+                .map(|v| [v[0] as f32, bottom, v[2] as f32]),
+        );
+        normals.extend(building.vertices.iter().map(|_| [0., -1., 0.]));
+        indices.extend(
+            building
+                .triangle_indices
+                .chunks(3)
+                .flat_map(|t| [t[0] + offset, t[2] + offset, t[1] + offset]),
+        );
+    }
+    let uvs: Vec<[f32; 2]> = vertices.iter().map(|p| [p[0], p[2]]).collect();
+    roof.insert_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        VertexAttributeValues::from(vertices),
     );
-    let uvs: Vec<[f32; 2]> = vertices.clone().iter().map(|p| [p[0], p[2]]).collect();
+    roof.insert_attribute(Mesh::ATTRIBUTE_NORMAL, VertexAttributeValues::from(normals));
     roof.insert_attribute(Mesh::ATTRIBUTE_UV_0, VertexAttributeValues::from(uvs));
-    let bs = building.triangle_indices.clone();
-    roof.set_indices(Some(Indices::U32(bs)));
+    roof.set_indices(Some(Indices::U32(indices)));
 
-    // let height = 0.5;
-    let translation = transform.translation + Vec3::new(0., height, 0.);
+    let translation = translate + Vec3::new(0., eave, 0.);
     let transform: Transform = Transform::from_translation(translation);
 
-    let handle: Handle<StandardMaterial> = match &building.class {
-        Some(c) => map_materials.roofs.get(c).unwrap().clone(),
-        None => map_materials.unknown_building_roof.clone(),
+    let roof_color = building.attrs.roof_color.as_deref().and_then(parse_color);
+    let handle: Handle<StandardMaterial> = match (roof_color, &building.class) {
+        (Some(color), _) => color_materials.get_or_add(color, materials),
+        (None, Some(c)) => map_materials.roofs.get(c).unwrap().clone(),
+        (None, None) => map_materials.unknown_building_roof.clone(),
     };
-    cmd.spawn((
-        PbrBundle {
-            mesh: meshes.add(roof),
-            material: handle,
-            transform,
-            ..Default::default()
-        },
-        building.clone(),
-    ));
+    let roof = cmd
+        .spawn((
+            PbrBundle {
+                mesh: meshes.add(roof),
+                material: handle,
+                transform,
+                ..Default::default()
+            },
+            building.clone(),
+            On::<Pointer<Click>>::send_event::<FeatureClicked>(),
+        ))
+        .id();
+
+    [walls, roof]
 }
 
 #[derive(Component, Debug)]
@@ -385,14 +451,35 @@ impl Wall {
         }
     }
 
-    pub fn new(line: &Vec<[f64; 2]>, height: f32) -> Self {
+    /// Exterior walls plus courtyard walls for each hole.
+    pub fn with_holes(
+        line: &Vec<[f64; 2]>,
+        holes: &[Vec<[f64; 2]>],
+        top: &dyn Fn([f64; 2]) -> f32,
+    ) -> Self {
+        let mut wall = Wall::new(line, top);
+        for hole in holes {
+            let inner = Wall::new(hole, top);
+            let offset = wall.vertices.len() as u32;
+            wall.points.extend(inner.points);
+            wall.norm.extend(inner.norm);
+            wall.vertices.extend(inner.vertices);
+            wall.normals.extend(inner.normals);
+            wall.uvs.extend(inner.uvs);
+            wall.indices
+                .extend(inner.indices.into_iter().map(|i| i + offset));
+        }
+        wall
+    }
+
+    /// Walls along `line`, `top(point)` high.
+    pub fn new(line: &Vec<[f64; 2]>, top: &dyn Fn([f64; 2]) -> f32) -> Self {
         let mut wall = Wall::empty();
         wall.points = line
             .iter()
             .map(|pos| Vec3::new(pos[0] as f32, 0., pos[1] as f32))
             .collect::<Vec<Vec3>>();
-
-        let heightv: Vec3 = Vec3::Y * height;
+        let tops: Vec<Vec3> = line.iter().map(|pos| Vec3::Y * top(*pos)).collect();
         let material_lengh = 1.;
         let mut len: f32 = 0.;
         let points_len = wall.points.len();
@@ -415,9 +502,9 @@ impl Wall {
                 let point: Vec3 = *p;
                 let point_next: Vec3 = wall.points[i_next];
                 wall.vertices.push((point).into());
-                wall.vertices.push((point + heightv).into());
+                wall.vertices.push((point + tops[i]).into());
                 wall.vertices.push((point_next).into());
-                wall.vertices.push((point_next + heightv).into());
+                wall.vertices.push((point_next + tops[i_next]).into());
 
                 let diff = point_next.sub(point).length();
                 wall.uvs.push([len / material_lengh, 0.]);
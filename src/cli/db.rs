@@ -1,40 +1,340 @@
+use clap::{Args, ValueEnum};
 use duckdb::Connection;
+use parquet::file::{reader::FileReader, serialized_reader::SerializedFileReader};
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
 
-pub fn cache_location(lon: f64, lat: f64, name: &str) {
-    let shift = 0.01;
-    let lon_max = lon + shift;
-    let lon_min = lon - shift;
-    let lat_max = lat + shift;
-    let lat_min = lat - shift;
-
-    // let polygon_str = format!("{lon_min} {lat_min}, {lon_min} {lat_max}, {lon_max} {lat_max}, {lon_max} {lat_min}, {lon_min} {lat_min}", );
-    // let where_str = format!("ST_Within(ST_GeomFromWkb(geometry), ST_Envelope(ST_GeomFromText('POLYGON(({polygon_str}))')))");
-    let where_str = format!("bbox.maxX > {lon_min} AND bbox.maxY > {lat_min} AND bbox.minX < {lon_max} AND bbox.minY < {lat_max}");
-
-    let path = "./data.duckdb";
-    let conn = Connection::open(&path).unwrap();
-    conn.execute_batch("INSTALL httpfs; LOAD httpfs;").unwrap();
-    conn.execute_batch("INSTALL spatial; LOAD spatial;")
-        .unwrap();
-
-    let lonlatname = format!("{lon}_{lat}_{name}");
-
-    let from_segment =
-        "read_parquet('/mnt/overture/theme=transportation/type=segment/*')".to_string();
-    let mut stmt = conn
-        .prepare(&format!(
-            "COPY (SELECT * FROM {from_segment} WHERE {where_str})
-            TO 'parquet/{lonlatname}_transportation.parquet' (FORMAT 'parquet')"
-        ))
-        .unwrap();
-    let _ = stmt.query([]).unwrap();
-
-    let from_building = "read_parquet('/mnt/overture/theme=buildings/type=building/*')".to_string();
-    let mut stmt = conn
-        .prepare(&format!(
-            "COPY (SELECT * FROM {from_building} WHERE {where_str})
-            TO 'parquet/{lonlatname}_building.parquet' (FORMAT 'parquet')"
-        ))
-        .unwrap();
-    let _ = stmt.query([]).unwrap();
+use crate::map_config::{ConfigError, Layer, MapConfig, ProjectionKind};
+use crate::projection::Projection;
+use crate::regions::{CacheRecord, LayerRecord, Region};
+use crate::sql::sql_string;
+
+#[derive(ValueEnum, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Existing {
+    /// Extract again
+    #[default]
+    Overwrite,
+    /// Keep the file and move on
+    Skip,
+}
+
+/// Area and source of an extract; layers and output paths come from `MapArgs`.
+#[derive(Args, Debug, Clone)]
+pub struct ExtractArgs {
+    /// Half the side of a square around lon/lat, in metres [default: 1000]
+    #[arg(long, conflicts_with = "bbox")]
+    pub radius: Option<f64>,
+    /// lon_min,lat_min,lon_max,lat_max instead of a square around lon/lat
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    pub bbox: Option<Vec<f64>>,
+    /// GeoJSON Polygon or MultiPolygon (bare, Feature or FeatureCollection),
+    /// only features intersecting it are kept
+    #[arg(long)]
+    pub clip: Option<PathBuf>,
+    /// Directory holding the Overture `theme=*/type=*` partitions
+    #[arg(long, default_value = "/mnt/overture")]
+    pub overture_root: String,
+    /// Release directory under the root, e.g. 2023-11-14-alpha.0
+    #[arg(long)]
+    pub release: Option<String>,
+    /// What to do with output files that already exist
+    #[arg(long, value_enum, default_value_t)]
+    pub existing: Existing,
+}
+
+const DEFAULT_RADIUS: f64 = 1000.;
+
+#[derive(Debug)]
+pub enum ExtractError {
+    DuckDb(duckdb::Error),
+    Io(PathBuf, std::io::Error),
+    Clip(PathBuf, String),
+    Invalid(&'static str, String),
+    Parquet(PathBuf, parquet::errors::ParquetError),
+    Join(tokio::task::JoinError),
+    Record(ConfigError),
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractError::DuckDb(e) => write!(f, "duckdb: {e}"),
+            ExtractError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            ExtractError::Clip(path, e) => write!(f, "clip {}: {e}", path.display()),
+            ExtractError::Invalid(field, reason) => write!(f, "invalid {field}: {reason}"),
+            ExtractError::Parquet(path, e) => write!(f, "{}: {e}", path.display()),
+            ExtractError::Join(e) => write!(f, "extract task: {e}"),
+            ExtractError::Record(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {}
+
+impl From<duckdb::Error> for ExtractError {
+    fn from(e: duckdb::Error) -> Self {
+        ExtractError::DuckDb(e)
+    }
+}
+
+impl ExtractArgs {
+    /// `read_parquet` glob of a layer.
+    pub fn source(&self, layer: Layer) -> String {
+        let (theme, kind) = layer.theme_type();
+        let root = self.overture_root.trim_end_matches('/');
+        match &self.release {
+            Some(release) => format!("{root}/{release}/theme={theme}/type={kind}/*"),
+            None => format!("{root}/theme={theme}/type={kind}/*"),
+        }
+    }
+
+    /// Fills what was not passed from the manifest entry.
+    pub fn with_region(&self, region: &Region) -> ExtractArgs {
+        let mut extract = self.clone();
+        if extract.bbox.is_none() && extract.radius.is_none() {
+            extract.bbox = region.bbox.map(|b| b.to_vec());
+            extract.radius = region.radius;
+        }
+        extract.clip = extract.clip.or(region.clip.clone());
+        extract
+    }
+}
+
+/// `[lon_min, lat_min, lon_max, lat_max]` of a square `radius` metres out from lon/lat.
+pub fn radius_bbox(lon: f64, lat: f64, radius: f64) -> [f64; 4] {
+    let projection = Projection::new(ProjectionKind::Local, lon, lat);
+    [
+        [-radius, -radius],
+        [radius, radius],
+        [-radius, radius],
+        [radius, -radius],
+    ]
+    .map(|xz| projection.inverse(xz))
+    .into_iter()
+    .fold(EMPTY_BBOX, |bbox, p| extend(bbox, p))
+}
+
+const EMPTY_BBOX: [f64; 4] = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+
+fn extend(bbox: [f64; 4], [lon, lat]: [f64; 2]) -> [f64; 4] {
+    [
+        bbox[0].min(lon),
+        bbox[1].min(lat),
+        bbox[2].max(lon),
+        bbox[3].max(lat),
+    ]
+}
+
+/// Polygon geometries of a GeoJSON file, as GeoJSON.
+fn clip_geometries(path: &Path) -> Result<Vec<serde_json::Value>, ExtractError> {
+    fn collect(value: &serde_json::Value, out: &mut Vec<serde_json::Value>) {
+        match value["type"].as_str() {
+            Some("FeatureCollection") => value["features"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .for_each(|f| collect(f, out)),
+            Some("Feature") => collect(&value["geometry"], out),
+            Some("Polygon" | "MultiPolygon") => out.push(value.clone()),
+            _ => {}
+        }
+    }
+
+    let text = std::fs::read_to_string(path).map_err(|e| ExtractError::Io(path.into(), e))?;
+    let json: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| ExtractError::Clip(path.into(), e.to_string()))?;
+    let mut geometries = vec![];
+    collect(&json, &mut geometries);
+    if geometries.is_empty() {
+        return Err(ExtractError::Clip(
+            path.into(),
+            "no Polygon or MultiPolygon".to_string(),
+        ));
+    }
+    Ok(geometries)
+}
+
+/// Bbox of every position in the geometries.
+fn geometries_bbox(geometries: &[serde_json::Value]) -> [f64; 4] {
+    fn visit(value: &serde_json::Value, bbox: &mut [f64; 4]) {
+        let Some(array) = value.as_array() else {
+            return;
+        };
+        match (
+            array.first().and_then(|v| v.as_f64()),
+            array.get(1).and_then(|v| v.as_f64()),
+        ) {
+            (Some(lon), Some(lat)) => *bbox = extend(*bbox, [lon, lat]),
+            _ => array.iter().for_each(|v| visit(v, bbox)),
+        }
+    }
+
+    let mut bbox = EMPTY_BBOX;
+    for geometry in geometries {
+        visit(&geometry["coordinates"], &mut bbox);
+    }
+    bbox
+}
+
+/// `bbox`, else a square of `radius`, else the clip polygon's bbox, else a
+/// square of `DEFAULT_RADIUS`.
+pub fn extract_bbox(
+    lon: f64,
+    lat: f64,
+    extract: &ExtractArgs,
+    clip: Option<&[serde_json::Value]>,
+) -> Result<[f64; 4], ExtractError> {
+    if let Some(radius) = extract.radius.filter(|r| !(*r > 0.)) {
+        return Err(ExtractError::Invalid(
+            "radius",
+            format!("{radius} is not > 0"),
+        ));
+    }
+    let bbox = match (&extract.bbox, extract.radius, clip) {
+        (Some(bbox), _, _) => <[f64; 4]>::try_from(bbox.as_slice()).map_err(|_| {
+            ExtractError::Invalid("bbox", format!("{} values, expected 4", bbox.len()))
+        })?,
+        (None, Some(radius), _) => radius_bbox(lon, lat, radius),
+        (None, None, Some(clip)) => geometries_bbox(clip),
+        (None, None, None) => radius_bbox(lon, lat, DEFAULT_RADIUS),
+    };
+    let [lon_min, lat_min, lon_max, lat_max] = bbox;
+    if !(lon_min < lon_max && lat_min < lat_max) {
+        return Err(ExtractError::Invalid(
+            "bbox",
+            format!("{lon_min},{lat_min},{lon_max},{lat_max} is empty"),
+        ));
+    }
+    Ok(bbox)
+}
+
+/// `bbox` of the area and the `WHERE` selecting its features.
+fn area_filter(
+    map_config: &MapConfig,
+    extract: &ExtractArgs,
+) -> Result<([f64; 4], String), ExtractError> {
+    let clip = extract.clip.as_deref().map(clip_geometries).transpose()?;
+    let bbox = extract_bbox(map_config.lon, map_config.lat, extract, clip.as_deref())?;
+    let [lon_min, lat_min, lon_max, lat_max] = bbox;
+
+    // The bbox struct column lets DuckDB skip row groups, the clip polygon is exact.
+    let mut where_str = format!("bbox.maxX > {lon_min} AND bbox.maxY > {lat_min} AND bbox.minX < {lon_max} AND bbox.minY < {lat_max}");
+    if let Some(clip) = &clip {
+        let intersects: Vec<String> = clip
+            .iter()
+            .map(|geometry| {
+                let geojson = sql_string(&geometry.to_string());
+                format!("ST_Intersects(ST_GeomFromWKB(geometry), ST_GeomFromGeoJSON({geojson}))")
+            })
+            .collect();
+        where_str += &format!(" AND ({})", intersects.join(" OR "));
+    }
+    Ok((bbox, where_str))
+}
+
+/// Copies the features of `source` matching `where_str` to `to`, unless it
+/// exists and `existing` is `Skip`. Opens its own connection so layers can
+/// run on separate threads. Rows are sorted by latitude into small row
+/// groups, which lets the viewer's parquet source skip those off a tile.
+fn extract_layer(
+    source: &str,
+    where_str: &str,
+    to: &Path,
+    existing: Existing,
+) -> Result<(), ExtractError> {
+    if existing == Existing::Skip && to.is_file() {
+        println!("{}: exists, skipped", to.display());
+        return Ok(());
+    }
+    if let Some(dir) = to.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| ExtractError::Io(dir.into(), e))?;
+    }
+    let conn = Connection::open_in_memory()?;
+    conn.execute_batch("INSTALL httpfs; LOAD httpfs;")?;
+    conn.execute_batch("INSTALL spatial; LOAD spatial;")?;
+    conn.execute_batch(&format!(
+        "COPY (SELECT * FROM read_parquet({}) WHERE {where_str}
+            ORDER BY bbox.minY, bbox.minX)
+        TO {} (FORMAT 'parquet', ROW_GROUP_SIZE 8192)",
+        sql_string(source),
+        sql_string(&to.display().to_string())
+    ))?;
+    println!("{}: written", to.display());
+    Ok(())
+}
+
+/// Row count from the parquet footer and a checksum of the whole file.
+fn layer_record(layer: Layer, path: &Path) -> Result<LayerRecord, ExtractError> {
+    let io = |e| ExtractError::Io(path.into(), e);
+    let file = File::open(path).map_err(io)?;
+    let rows = SerializedFileReader::new(file.try_clone().map_err(io)?)
+        .map_err(|e| ExtractError::Parquet(path.into(), e))?
+        .metadata()
+        .file_metadata()
+        .num_rows() as u64;
+    let mut hasher = blake3::Hasher::new();
+    let bytes = std::io::copy(&mut BufReader::new(file), &mut hasher).map_err(io)?;
+    Ok(LayerRecord {
+        layer,
+        file: path.into(),
+        rows,
+        bytes,
+        blake3: hasher.finalize().to_hex().to_string(),
+    })
+}
+
+pub fn cache_location(map_config: &MapConfig, extract: &ExtractArgs) -> Result<(), ExtractError> {
+    let (_, where_str) = area_filter(map_config, extract)?;
+    for layer in map_config.layers.iter() {
+        extract_layer(
+            &extract.source(*layer),
+            &where_str,
+            &map_config.parquet_path(*layer),
+            extract.existing,
+        )?;
+    }
+    Ok(())
+}
+
+/// Like `cache_location`, with each layer on a blocking thread of the
+/// runtime, then records row counts and checksums next to the files.
+pub async fn cache_region(
+    map_config: &MapConfig,
+    extract: &ExtractArgs,
+) -> Result<CacheRecord, ExtractError> {
+    let (bbox, where_str) = area_filter(map_config, extract)?;
+    let tasks: Vec<_> = map_config
+        .layers
+        .iter()
+        .map(|layer| {
+            let (layer, source, where_str, to, existing) = (
+                *layer,
+                extract.source(*layer),
+                where_str.clone(),
+                map_config.parquet_path(*layer),
+                extract.existing,
+            );
+            tokio::task::spawn_blocking(move || {
+                extract_layer(&source, &where_str, &to, existing)?;
+                layer_record(layer, &to)
+            })
+        })
+        .collect();
+
+    let mut layers = vec![];
+    for task in tasks {
+        layers.push(task.await.map_err(ExtractError::Join)??);
+    }
+    let record = CacheRecord {
+        region: map_config.name.clone(),
+        release: extract.release.clone(),
+        bbox,
+        layers,
+    };
+    record
+        .save(&map_config.record_path())
+        .map_err(ExtractError::Record)?;
+    Ok(record)
 }
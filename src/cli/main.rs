@@ -1,10 +1,20 @@
 use clap::{Args, Parser, Subcommand};
+use darkmap::{geo_util, map_config, projection, regions, sql};
+use std::path::PathBuf;
 
-use crate::{db::cache_location, geometry::check_wkb, overture_types::get_schema_json};
+use crate::{
+    db::{cache_location, cache_region, Existing, ExtractArgs},
+    geometry::check_wkb,
+    map_config::{parse_area_name, Layer, MapArgs, MapConfig},
+    overture_types::get_schema_json,
+    regions::{RegionManifest, REGIONS_PATH},
+    stats::{area_stats, print_table, StatsFormat},
+};
 
 mod db;
 mod geometry;
 mod overture_types;
+mod stats;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -19,6 +29,10 @@ enum Commands {
     CheckWkb(CheckWkbArgs),
     GetSchemaJson,
     Location(LocationArgs),
+    /// Extracts every area of the regions manifest
+    CacheAll(CacheAllArgs),
+    /// Feature counts, classes, heights and road length of a cached area
+    Stats(StatsArgs),
 }
 
 #[derive(Args)]
@@ -27,13 +41,118 @@ struct CheckWkbArgs {
 }
 #[derive(Args)]
 struct LocationArgs {
-    lon: String, // x
-    lat: String, // y
-    name: String,
+    #[command(flatten)]
+    map: MapArgs,
+    #[command(flatten)]
+    extract: ExtractArgs,
+}
+#[derive(Args)]
+struct CacheAllArgs {
+    /// Regions manifest
+    #[arg(long, default_value = REGIONS_PATH)]
+    regions: PathBuf,
+    /// Only these regions
+    #[arg(long, value_delimiter = ',')]
+    only: Option<Vec<String>>,
+    /// Overrides the manifest's `overture_root`
+    #[arg(long)]
+    overture_root: Option<String>,
+    /// Overrides the manifest's `release`
+    #[arg(long)]
+    release: Option<String>,
+    /// What to do with output files that already exist
+    #[arg(long, value_enum, default_value_t)]
+    existing: Existing,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    /// `{lon}_{lat}_{name}` of the cached files, or a region of the manifest
+    area: String,
+    /// Directory with cached parquet files
+    #[arg(long)]
+    parquet_dir: Option<PathBuf>,
+    /// Regions manifest [default: regions.yaml]
+    #[arg(long)]
+    regions: Option<PathBuf>,
+    /// Layers to report on [default: all cached]
+    #[arg(long, value_delimiter = ',')]
+    layers: Option<Vec<Layer>>,
+    #[arg(long, value_enum, default_value_t)]
+    format: StatsFormat,
+}
+
+fn stats(args: &StatsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let map_args = match parse_area_name(&args.area) {
+        Some((lon, lat, name)) => MapArgs {
+            lon: Some(lon),
+            lat: Some(lat),
+            name: Some(name.to_string()),
+            ..Default::default()
+        },
+        None => MapArgs {
+            region: Some(args.area.clone()),
+            regions: args.regions.clone(),
+            ..Default::default()
+        },
+    };
+    let map_config = MapConfig::load(&MapArgs {
+        parquet_dir: args.parquet_dir.clone(),
+        layers: args.layers.clone(),
+        ..map_args
+    })?;
+    let stats = area_stats(&map_config)?;
+    match args.format {
+        StatsFormat::Table => print_table(&stats),
+        StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+    }
+    Ok(())
+}
+
+async fn cache_all(args: &CacheAllArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let manifest = RegionManifest::load(&args.regions)?;
+    if let Some(only) = &args.only {
+        for name in only {
+            manifest.get(name)?;
+        }
+    }
+    let base = ExtractArgs {
+        radius: None,
+        bbox: None,
+        clip: None,
+        overture_root: args
+            .overture_root
+            .clone()
+            .or(manifest.overture_root.clone())
+            .unwrap_or_else(|| "/mnt/overture".to_string()),
+        release: args.release.clone().or(manifest.release.clone()),
+        existing: args.existing,
+    };
+    for region in manifest.regions.iter() {
+        if args
+            .only
+            .as_ref()
+            .is_some_and(|only| !only.contains(&region.name))
+        {
+            continue;
+        }
+        let map_config = MapConfig::load(&MapArgs {
+            region: Some(region.name.clone()),
+            regions: Some(args.regions.clone()),
+            ..Default::default()
+        })?;
+        println!("{}", map_config.area_name());
+        let record = cache_region(&map_config, &base.with_region(region)).await?;
+        for layer in record.layers.iter() {
+            println!("  {:?}: {} rows, {}", layer.layer, layer.rows, layer.blake3);
+        }
+    }
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() {
+    dotenv::dotenv().ok();
     let cli = Cli::parse();
 
     match &cli.command {
@@ -50,10 +169,34 @@ async fn main() {
         }
         Commands::Location(args) => {
             println!("Location start");
-            let lon = args.lon.parse::<f64>().expect("lon to be f64");
-            let lat = args.lat.parse::<f64>().expect("lat to be f64");
-            cache_location(lon, lat, &args.name);
+            let map_config = match MapConfig::load(&args.map) {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("cli location: {e}");
+                    std::process::exit(2);
+                }
+            };
+            let extract = match &map_config.region {
+                Some(region) => args.extract.with_region(region),
+                None => args.extract.clone(),
+            };
+            if let Err(e) = cache_location(&map_config, &extract) {
+                eprintln!("cli location: {e}");
+                std::process::exit(2);
+            }
             println!("Location end");
         }
+        Commands::Stats(args) => {
+            if let Err(e) = stats(args) {
+                eprintln!("cli stats: {e}");
+                std::process::exit(2);
+            }
+        }
+        Commands::CacheAll(args) => {
+            if let Err(e) = cache_all(args).await {
+                eprintln!("cli cache-all: {e}");
+                std::process::exit(2);
+            }
+        }
     }
 }
@@ -0,0 +1,490 @@
+use clap::ValueEnum;
+use geo::{BoundingRect, GeodesicLength};
+use geo_types::Geometry;
+use geozero::wkb::{FromWkb, WkbDialect};
+use parquet::file::serialized_reader::SerializedFileReader;
+use parquet::record::{Field, Row};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::path::PathBuf;
+
+use crate::geo_util::geometry_type;
+use crate::map_config::{Layer, MapConfig};
+
+#[derive(ValueEnum, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum StatsFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+#[derive(Debug)]
+pub enum StatsError {
+    Io(PathBuf, std::io::Error),
+    Parquet(PathBuf, parquet::errors::ParquetError),
+    /// None of the area's layers is cached.
+    Empty(String),
+}
+
+impl fmt::Display for StatsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatsError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            StatsError::Parquet(path, e) => write!(f, "{}: {e}", path.display()),
+            StatsError::Empty(area) => write!(f, "no cached layers for {area}"),
+        }
+    }
+}
+
+impl std::error::Error for StatsError {}
+
+/// Min, max, mean and percentiles of the non-null values of a column.
+#[derive(Serialize, Debug)]
+pub struct Distribution {
+    pub min: f64,
+    pub p10: f64,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+    pub p90: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl Distribution {
+    fn new(mut values: Vec<f64>) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        let at = |q: f64| values[((values.len() - 1) as f64 * q).round() as usize];
+        Some(Distribution {
+            min: values[0],
+            p10: at(0.1),
+            p25: at(0.25),
+            median: at(0.5),
+            p75: at(0.75),
+            p90: at(0.9),
+            max: values[values.len() - 1],
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+        })
+    }
+}
+
+/// `building` or `building_part` columns. Classes are the raw Overture
+/// values `BuildingClass` parses, `(none)` where missing.
+#[derive(Serialize, Debug, Default)]
+pub struct BuildingStats {
+    pub classes: BTreeMap<String, u64>,
+    pub with_height: u64,
+    pub height: Option<Distribution>,
+    pub with_floors: u64,
+    /// Buildings per `numFloors`.
+    pub floors: BTreeMap<i64, u64>,
+}
+
+/// `segment` columns. Classes are the raw `road.class` values `RoadClass`
+/// parses, segments without `road` (rail, water) are not counted.
+#[derive(Serialize, Debug, Default)]
+pub struct RoadStats {
+    pub classes: BTreeMap<String, u64>,
+    /// Geodesic, on WGS84.
+    pub length_m: f64,
+    pub length_m_by_class: BTreeMap<String, f64>,
+    pub without_road: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LayerStats {
+    pub layer: Layer,
+    pub file: PathBuf,
+    pub rows: u64,
+    /// Decoded WKB types, `invalid` if it does not decode.
+    pub geometry_types: BTreeMap<String, u64>,
+    /// `[lon_min, lat_min, lon_max, lat_max]` of the geometries.
+    pub bbox: Option<[f64; 4]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buildings: Option<BuildingStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roads: Option<RoadStats>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AreaStats {
+    pub area: String,
+    /// Over all cached layers.
+    pub bbox: Option<[f64; 4]>,
+    pub layers: Vec<LayerStats>,
+    /// Enabled layers without a cached file.
+    pub missing: Vec<Layer>,
+}
+
+fn as_f64(field: &Field) -> Option<f64> {
+    match field {
+        Field::Double(v) => Some(*v),
+        Field::Float(v) => Some(*v as f64),
+        Field::Int(v) => Some(*v as f64),
+        Field::Long(v) => Some(*v as f64),
+        _ => None,
+    }
+}
+
+fn as_i64(field: &Field) -> Option<i64> {
+    match field {
+        Field::Int(v) => Some(*v as i64),
+        Field::Long(v) => Some(*v),
+        Field::Short(v) => Some(*v as i64),
+        _ => None,
+    }
+}
+
+fn get<'a>(row: &'a Row, name: &str) -> Option<&'a Field> {
+    row.get_column_iter()
+        .find(|(n, _)| n.as_str() == name)
+        .map(|(_, f)| f)
+}
+
+fn union(a: Option<[f64; 4]>, b: [f64; 4]) -> [f64; 4] {
+    match a {
+        Some(a) => [
+            a[0].min(b[0]),
+            a[1].min(b[1]),
+            a[2].max(b[2]),
+            a[3].max(b[3]),
+        ],
+        None => b,
+    }
+}
+
+fn class_key(class: Option<String>) -> String {
+    class.unwrap_or_else(|| "(none)".to_string())
+}
+
+impl BuildingStats {
+    /// Counts a row, its height goes to `heights` for the distribution.
+    fn count(
+        &mut self,
+        class: Option<String>,
+        height: Option<f64>,
+        floors: Option<i64>,
+        heights: &mut Vec<f64>,
+    ) {
+        *self.classes.entry(class_key(class)).or_default() += 1;
+        if let Some(height) = height {
+            self.with_height += 1;
+            heights.push(height);
+        }
+        if let Some(floors) = floors {
+            self.with_floors += 1;
+            *self.floors.entry(floors).or_default() += 1;
+        }
+    }
+}
+
+impl RoadStats {
+    /// Counts a segment row, `class` is the row's own for releases without
+    /// `road.class`.
+    fn count(
+        &mut self,
+        road: Option<serde_json::Value>,
+        class: Option<String>,
+        geometry: Option<&Geometry>,
+    ) {
+        let Some(road) = road else {
+            self.without_road += 1;
+            return;
+        };
+        let class = class_key(road["class"].as_str().map(String::from).or(class));
+        let length = match geometry {
+            Some(Geometry::LineString(line)) => line.geodesic_length(),
+            Some(Geometry::MultiLineString(lines)) => lines.geodesic_length(),
+            _ => 0.,
+        };
+        self.length_m += length;
+        *self.length_m_by_class.entry(class.clone()).or_default() += length;
+        *self.classes.entry(class).or_default() += 1;
+    }
+}
+
+fn layer_stats(layer: Layer, path: PathBuf) -> Result<LayerStats, StatsError> {
+    let file = File::open(&path).map_err(|e| StatsError::Io(path.clone(), e))?;
+    let reader =
+        SerializedFileReader::new(file).map_err(|e| StatsError::Parquet(path.clone(), e))?;
+
+    let is_building = matches!(layer, Layer::Buildings | Layer::BuildingParts);
+    let mut buildings = BuildingStats::default();
+    let mut heights = vec![];
+    let mut roads = RoadStats::default();
+    let mut stats = LayerStats {
+        layer,
+        file: path.clone(),
+        rows: 0,
+        geometry_types: BTreeMap::new(),
+        bbox: None,
+        buildings: None,
+        roads: None,
+    };
+
+    for row in reader {
+        let row = row.map_err(|e| StatsError::Parquet(path.clone(), e))?;
+        stats.rows += 1;
+
+        let geometry = match get(&row, "geometry") {
+            Some(Field::Bytes(bytes)) => {
+                Geometry::from_wkb(&mut std::io::Cursor::new(bytes.data()), WkbDialect::Wkb).ok()
+            }
+            _ => None,
+        };
+        let type_name = geometry.as_ref().map_or("invalid", geometry_type);
+        *stats
+            .geometry_types
+            .entry(type_name.to_string())
+            .or_default() += 1;
+        if let Some(rect) = geometry.as_ref().and_then(|g| g.bounding_rect()) {
+            let bbox = [rect.min().x, rect.min().y, rect.max().x, rect.max().y];
+            stats.bbox = Some(union(stats.bbox, bbox));
+        }
+
+        let class = match get(&row, "class") {
+            Some(Field::Str(s)) => Some(s.clone()),
+            _ => None,
+        };
+        if is_building {
+            buildings.count(
+                class,
+                get(&row, "height").and_then(as_f64),
+                get(&row, "numFloors").and_then(as_i64),
+                &mut heights,
+            );
+        } else if layer == Layer::Transportation {
+            // `road` is a JSON string in the releases `query_transportation` reads.
+            let road: Option<serde_json::Value> = match get(&row, "road") {
+                Some(Field::Str(s)) => serde_json::from_str(s).ok(),
+                Some(Field::Null) | None => None,
+                Some(f) => Some(f.to_json_value()),
+            };
+            roads.count(road, class, geometry.as_ref());
+        }
+    }
+
+    if is_building {
+        buildings.height = Distribution::new(heights);
+        stats.buildings = Some(buildings);
+    } else if layer == Layer::Transportation {
+        stats.roads = Some(roads);
+    }
+    Ok(stats)
+}
+
+pub fn area_stats(map_config: &MapConfig) -> Result<AreaStats, StatsError> {
+    let mut stats = AreaStats {
+        area: map_config.area_name(),
+        bbox: None,
+        layers: vec![],
+        missing: vec![],
+    };
+    for layer in map_config.layers.iter() {
+        let path = map_config.parquet_path(*layer);
+        if !path.is_file() {
+            stats.missing.push(*layer);
+            continue;
+        }
+        let layer_stats = layer_stats(*layer, path)?;
+        if let Some(bbox) = layer_stats.bbox {
+            stats.bbox = Some(union(stats.bbox, bbox));
+        }
+        stats.layers.push(layer_stats);
+    }
+    if stats.layers.is_empty() {
+        return Err(StatsError::Empty(stats.area));
+    }
+    Ok(stats)
+}
+
+fn format_bbox(bbox: Option<[f64; 4]>) -> String {
+    match bbox {
+        Some([lon_min, lat_min, lon_max, lat_max]) => {
+            format!("{lon_min:.6},{lat_min:.6},{lon_max:.6},{lat_max:.6}")
+        }
+        None => "-".to_string(),
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.
+    } else {
+        part as f64 * 100. / total as f64
+    }
+}
+
+/// Two-column rows under a heading, counts right-aligned.
+fn print_counts<K: fmt::Display, V: fmt::Display>(
+    heading: &str,
+    rows: impl IntoIterator<Item = (K, V)>,
+) {
+    println!("  {heading}");
+    for (key, value) in rows {
+        println!("    {:<24} {value:>12}", key.to_string());
+    }
+}
+
+pub fn print_table(stats: &AreaStats) {
+    println!("area  {}", stats.area);
+    println!("bbox  {}", format_bbox(stats.bbox));
+    for layer in stats.layers.iter() {
+        println!();
+        println!("{} ({} rows)", layer.layer.file_suffix(), layer.rows);
+        println!("  bbox  {}", format_bbox(layer.bbox));
+        print_counts("geometry", layer.geometry_types.iter());
+        if let Some(buildings) = &layer.buildings {
+            let mut classes: Vec<_> = buildings.classes.iter().collect();
+            classes.sort_by(|a, b| b.1.cmp(a.1));
+            print_counts("class", classes);
+            println!(
+                "  height  {}/{} ({:.1}%)",
+                buildings.with_height,
+                layer.rows,
+                percent(buildings.with_height, layer.rows)
+            );
+            if let Some(h) = &buildings.height {
+                println!(
+                    "    min {:.1}  p10 {:.1}  p25 {:.1}  median {:.1}  p75 {:.1}  p90 {:.1}  max {:.1}  mean {:.1}",
+                    h.min, h.p10, h.p25, h.median, h.p75, h.p90, h.max, h.mean
+                );
+            }
+            println!(
+                "  floors  {}/{} ({:.1}%)",
+                buildings.with_floors,
+                layer.rows,
+                percent(buildings.with_floors, layer.rows)
+            );
+            if !buildings.floors.is_empty() {
+                print_counts("num_floors", buildings.floors.iter());
+            }
+        }
+        if let Some(roads) = &layer.roads {
+            let mut classes: Vec<_> = roads.classes.iter().collect();
+            classes.sort_by(|a, b| b.1.cmp(a.1));
+            print_counts(
+                "class (segments, m)",
+                classes.into_iter().map(|(class, count)| {
+                    let length = roads.length_m_by_class.get(class).copied().unwrap_or(0.);
+                    (class, format!("{count} {length:>10.0}"))
+                }),
+            );
+            println!("  length  {:.0} m", roads.length_m);
+            if roads.without_road > 0 {
+                println!("  without road  {}", roads.without_road);
+            }
+        }
+    }
+    if !stats.missing.is_empty() {
+        println!();
+        let missing: Vec<&str> = stats.missing.iter().map(Layer::file_suffix).collect();
+        println!("not cached: {}", missing.join(", "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo_types::{line_string, LineString};
+    use serde_json::json;
+
+    #[test]
+    fn distribution_percentiles() {
+        let d = Distribution::new((1..=11).rev().map(f64::from).collect()).unwrap();
+        assert_eq!(
+            [d.min, d.p10, d.p25, d.median, d.p75, d.p90, d.max, d.mean],
+            [1., 2., 4., 6., 9., 10., 11., 6.]
+        );
+        let one = Distribution::new(vec![7.]).unwrap();
+        assert_eq!([one.min, one.median, one.max, one.mean], [7.; 4]);
+        assert!(Distribution::new(vec![]).is_none());
+    }
+
+    #[test]
+    fn counts_building_classes_and_floors() {
+        let mut stats = BuildingStats::default();
+        let mut heights = vec![];
+        let rows = [
+            (Some("residential"), Some(9.), Some(3)),
+            (Some("residential"), None, Some(3)),
+            (Some("commercial"), Some(20.), None),
+            (None, None, Some(1)),
+        ];
+        for (class, height, floors) in rows {
+            stats.count(class.map(String::from), height, floors, &mut heights);
+        }
+        assert_eq!(
+            stats.classes.into_iter().collect::<Vec<_>>(),
+            [
+                ("(none)".to_string(), 1),
+                ("commercial".to_string(), 1),
+                ("residential".to_string(), 2)
+            ]
+        );
+        assert_eq!((stats.with_height, stats.with_floors), (2, 3));
+        assert_eq!(
+            stats.floors.into_iter().collect::<Vec<_>>(),
+            [(1, 1), (3, 2)]
+        );
+        assert_eq!(heights, [9., 20.]);
+    }
+
+    #[test]
+    fn geodesic_road_length() {
+        let mut stats = RoadStats::default();
+        // A degree of longitude on the equator, 111.32 km on WGS84.
+        let equator: LineString = line_string![(x: 0., y: 0.), (x: 0.5, y: 0.), (x: 1., y: 0.)];
+        let equator = Geometry::LineString(equator);
+        stats.count(Some(json!({"class": "primary"})), None, Some(&equator));
+        stats.count(Some(json!({})), Some("footway".to_string()), Some(&equator));
+        stats.count(None, Some("rail".to_string()), Some(&equator));
+
+        assert!(
+            (stats.length_m - 2. * 111_319.49).abs() < 1.,
+            "{}",
+            stats.length_m
+        );
+        assert!((stats.length_m_by_class["primary"] - 111_319.49).abs() < 1.);
+        assert_eq!(stats.classes["footway"], 1);
+        assert!(!stats.classes.contains_key("rail"));
+        assert_eq!(stats.without_road, 1);
+    }
+
+    #[test]
+    fn json_shape() {
+        let stats = AreaStats {
+            area: "13.4_52.52_berlin".to_string(),
+            bbox: Some([13.3, 52.5, 13.5, 52.6]),
+            layers: vec![LayerStats {
+                layer: Layer::BuildingParts,
+                file: PathBuf::from("parquet/13.4_52.52_berlin_building_part.parquet"),
+                rows: 2,
+                geometry_types: [("Polygon".to_string(), 2)].into_iter().collect(),
+                bbox: None,
+                buildings: Some(BuildingStats {
+                    height: Distribution::new(vec![3., 5.]),
+                    ..Default::default()
+                }),
+                roads: None,
+            }],
+            missing: vec![Layer::LandUse],
+        };
+        let value = serde_json::to_value(&stats).unwrap();
+        assert_eq!(value["area"], "13.4_52.52_berlin");
+        assert_eq!(value["bbox"], json!([13.3, 52.5, 13.5, 52.6]));
+        assert_eq!(value["missing"], json!(["land_use"]));
+        let layer = &value["layers"][0];
+        assert_eq!(layer["layer"], "building_parts");
+        assert_eq!(layer["geometry_types"], json!({"Polygon": 2}));
+        assert_eq!(layer["bbox"], json!(null));
+        assert!(layer.get("roads").is_none());
+        assert_eq!(layer["buildings"]["height"]["median"], 5.);
+        assert_eq!(layer["buildings"]["floors"], json!({}));
+    }
+}
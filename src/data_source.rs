@@ -0,0 +1,125 @@
+use duckdb::Connection;
+use std::sync::Mutex;
+
+use crate::building::Building;
+use crate::error::{LoadReport, MapLoadError};
+use crate::ground::GroundArea;
+use crate::place::Place;
+use crate::query_base::{query_base, BaseQueryParams};
+use crate::query_buildings::{query_building_parts, query_buildings, BuildingsQueryParams};
+use crate::query_places::{query_places, PlacesQueryParams};
+use crate::query_transportation::{query_transportation, TransportationQueryParams};
+use crate::transportation::Segment;
+
+/// Where buildings and segments come from. Implementations must return the
+/// same features for the same params, tiles rely on it to load each feature once.
+pub trait MapDataSource: Send + Sync {
+    fn buildings(
+        &self,
+        params: BuildingsQueryParams,
+    ) -> Result<(Vec<Building>, LoadReport), MapLoadError>;
+
+    /// `building_part` features, with `attrs.building_id` set.
+    fn building_parts(
+        &self,
+        params: BuildingsQueryParams,
+    ) -> Result<(Vec<Building>, LoadReport), MapLoadError>;
+
+    fn segments(
+        &self,
+        params: TransportationQueryParams,
+    ) -> Result<(Vec<Segment>, LoadReport), MapLoadError>;
+
+    fn places(&self, params: PlacesQueryParams) -> Result<(Vec<Place>, LoadReport), MapLoadError>;
+
+    fn base(&self, params: BaseQueryParams) -> Result<(Vec<GroundArea>, LoadReport), MapLoadError>;
+}
+
+/// Reads through DuckDB. The `httpfs` and `spatial` extensions are installed
+/// and loaded once, on the first query; each query then runs on its own
+/// connection to that in-memory database, so tiles still load side by side.
+#[derive(Default)]
+pub struct DuckDbSource {
+    db: Mutex<Option<Connection>>,
+}
+
+impl DuckDbSource {
+    fn connection(&self) -> Result<Connection, MapLoadError> {
+        let mut db = self.db.lock().unwrap();
+        if db.is_none() {
+            let conn = Connection::open_in_memory()?;
+            conn.execute_batch("INSTALL httpfs; LOAD httpfs; INSTALL spatial; LOAD spatial;")?;
+            *db = Some(conn);
+        }
+        Ok(db.as_ref().unwrap().try_clone()?)
+    }
+}
+
+impl MapDataSource for DuckDbSource {
+    fn buildings(
+        &self,
+        params: BuildingsQueryParams,
+    ) -> Result<(Vec<Building>, LoadReport), MapLoadError> {
+        query_buildings(&self.connection()?, params)
+    }
+
+    fn building_parts(
+        &self,
+        params: BuildingsQueryParams,
+    ) -> Result<(Vec<Building>, LoadReport), MapLoadError> {
+        query_building_parts(&self.connection()?, params)
+    }
+
+    fn segments(
+        &self,
+        params: TransportationQueryParams,
+    ) -> Result<(Vec<Segment>, LoadReport), MapLoadError> {
+        query_transportation(&self.connection()?, params)
+    }
+
+    fn places(&self, params: PlacesQueryParams) -> Result<(Vec<Place>, LoadReport), MapLoadError> {
+        query_places(&self.connection()?, params)
+    }
+
+    fn base(&self, params: BaseQueryParams) -> Result<(Vec<GroundArea>, LoadReport), MapLoadError> {
+        query_base(&self.connection()?, params)
+    }
+}
+
+/// SQL predicate selecting features whose bbox centre lies in
+/// `[lon_min, lat_min, lon_max, lat_max)`, so that a feature crossing a tile
+/// border is loaded exactly once.
+pub fn bbox_where(bbox: [f64; 4]) -> String {
+    let [lon_min, lat_min, lon_max, lat_max] = bbox;
+    format!(
+        "(bbox.minX + bbox.maxX) / 2 >= {lon_min} AND (bbox.minX + bbox.maxX) / 2 < {lon_max}
+        AND (bbox.minY + bbox.maxY) / 2 >= {lat_min} AND (bbox.minY + bbox.maxY) / 2 < {lat_max}"
+    )
+}
+
+/// Same test as `bbox_where` for a feature bbox `[min_x, min_y, max_x, max_y]`.
+pub fn bbox_contains_center(bbox: [f64; 4], feature_bbox: [f64; 4]) -> bool {
+    let x = (feature_bbox[0] + feature_bbox[2]) / 2.;
+    let y = (feature_bbox[1] + feature_bbox[3]) / 2.;
+    x >= bbox[0] && x < bbox[2] && y >= bbox[1] && y < bbox[3]
+}
+
+/// SQL predicate selecting features whose bbox intersects
+/// `[lon_min, lat_min, lon_max, lat_max)`. A feature crossing tile borders is
+/// loaded by every tile it touches, for the large `base` polygons.
+pub fn bbox_intersects_where(bbox: [f64; 4]) -> String {
+    let [lon_min, lat_min, lon_max, lat_max] = bbox;
+    format!(
+        "bbox.maxX >= {lon_min} AND bbox.minX < {lon_max}
+        AND bbox.maxY >= {lat_min} AND bbox.minY < {lat_max}"
+    )
+}
+
+/// Same test as `bbox_intersects_where` for a feature bbox
+/// `[min_x, min_y, max_x, max_y]`.
+pub fn bbox_intersects(bbox: [f64; 4], feature_bbox: [f64; 4]) -> bool {
+    feature_bbox[2] >= bbox[0]
+        && feature_bbox[0] < bbox[2]
+        && feature_bbox[3] >= bbox[1]
+        && feature_bbox[1] < bbox[3]
+}
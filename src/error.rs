@@ -0,0 +1,90 @@
+use bevy::log::warn;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum MapLoadError {
+    Io(std::io::Error),
+    DuckDb(duckdb::Error),
+    Parquet(parquet::errors::ParquetError),
+    Wkb(geozero::error::GeozeroError),
+    UnsupportedGeometry(&'static str),
+    Schema(String),
+}
+
+impl fmt::Display for MapLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapLoadError::Io(e) => write!(f, "io: {e}"),
+            MapLoadError::DuckDb(e) => write!(f, "duckdb: {e}"),
+            MapLoadError::Parquet(e) => write!(f, "parquet: {e}"),
+            MapLoadError::Wkb(e) => write!(f, "wkb: {e}"),
+            MapLoadError::UnsupportedGeometry(t) => write!(f, "unsupported geometry: {t}"),
+            MapLoadError::Schema(e) => write!(f, "schema mismatch: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for MapLoadError {}
+
+impl From<std::io::Error> for MapLoadError {
+    fn from(e: std::io::Error) -> Self {
+        MapLoadError::Io(e)
+    }
+}
+
+impl From<duckdb::Error> for MapLoadError {
+    fn from(e: duckdb::Error) -> Self {
+        match e {
+            duckdb::Error::InvalidColumnType(..)
+            | duckdb::Error::InvalidColumnName(_)
+            | duckdb::Error::InvalidColumnIndex(_) => MapLoadError::Schema(e.to_string()),
+            e => MapLoadError::DuckDb(e),
+        }
+    }
+}
+
+impl From<parquet::errors::ParquetError> for MapLoadError {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        MapLoadError::Parquet(e)
+    }
+}
+
+impl From<geozero::error::GeozeroError> for MapLoadError {
+    fn from(e: geozero::error::GeozeroError) -> Self {
+        MapLoadError::Wkb(e)
+    }
+}
+
+impl From<serde_json::Error> for MapLoadError {
+    fn from(e: serde_json::Error) -> Self {
+        MapLoadError::Schema(e.to_string())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SkippedFeature {
+    pub id: Option<String>,
+    pub reason: String,
+}
+
+/// What a query loaded and which features it had to skip.
+#[derive(Debug, Default, Clone)]
+pub struct LoadReport {
+    pub loaded: usize,
+    pub skipped: Vec<SkippedFeature>,
+}
+
+impl LoadReport {
+    pub fn skip(&mut self, id: Option<&str>, error: MapLoadError) {
+        warn!("skipped feature {}: {error}", id.unwrap_or("?"));
+        self.skipped.push(SkippedFeature {
+            id: id.map(str::to_string),
+            reason: error.to_string(),
+        });
+    }
+
+    pub fn merge(&mut self, other: LoadReport) {
+        self.loaded += other.loaded;
+        self.skipped.extend(other.skipped);
+    }
+}
@@ -0,0 +1,283 @@
+use geo::algorithm::orient::{Direction, Orient};
+use geo::line_intersection::{line_intersection, LineIntersection};
+use geo::{Area, Contains};
+use geo_types::{Coord, Line, LineString, Polygon};
+
+/// Consecutive points closer than this (degrees, ~1 mm) make a zero-length edge.
+const EPSILON: f64 = 1e-8;
+
+/// What `repair_footprint` had to change, kept on the `Building` for QA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QaFlag {
+    /// Exterior wasn't counter-clockwise or a hole wasn't clockwise.
+    Reoriented,
+    /// Repeated consecutive coordinates removed.
+    DuplicatePoints,
+    /// Edges shorter than `EPSILON` collapsed.
+    ZeroLengthEdges,
+    /// Ring wasn't closed.
+    Unclosed,
+    /// Exterior crossed itself, split at the crossings into simple rings,
+    /// or kept as is where edges overlap.
+    SelfIntersection,
+    /// Hole crossed itself, had under 3 distinct points or wasn't inside the
+    /// exterior, dropped.
+    DroppedHole,
+}
+
+impl QaFlag {
+    pub fn label(&self) -> &'static str {
+        match self {
+            QaFlag::Reoriented => "reoriented",
+            QaFlag::DuplicatePoints => "duplicate points",
+            QaFlag::ZeroLengthEdges => "zero-length edges",
+            QaFlag::Unclosed => "unclosed ring",
+            QaFlag::SelfIntersection => "self-intersection",
+            QaFlag::DroppedHole => "dropped hole",
+        }
+    }
+}
+
+fn flag(qa: &mut Vec<QaFlag>, f: QaFlag) {
+    if !qa.contains(&f) {
+        qa.push(f);
+    }
+}
+
+/// Closed ring without repeated or near-identical consecutive points.
+fn clean_ring(ring: &LineString, qa: &mut Vec<QaFlag>) -> LineString {
+    let mut coords: Vec<Coord> = Vec::with_capacity(ring.0.len());
+    for c in ring.coords() {
+        match coords.last() {
+            Some(last) if last == c => flag(qa, QaFlag::DuplicatePoints),
+            Some(last) if (c.x - last.x).hypot(c.y - last.y) < EPSILON => {
+                flag(qa, QaFlag::ZeroLengthEdges)
+            }
+            _ => coords.push(*c),
+        }
+    }
+    if coords.len() > 1 && coords.first() != coords.last() {
+        let (first, last) = (coords[0], coords[coords.len() - 1]);
+        if (first.x - last.x).hypot(first.y - last.y) < EPSILON {
+            // Closing point drifted, snap it.
+            let n = coords.len();
+            coords[n - 1] = first;
+            flag(qa, QaFlag::ZeroLengthEdges);
+        } else {
+            coords.push(first);
+            flag(qa, QaFlag::Unclosed);
+        }
+    }
+    LineString(coords)
+}
+
+/// Any two non-adjacent edges touching.
+fn self_intersects(ring: &LineString) -> bool {
+    let lines: Vec<Line> = ring.lines().collect();
+    let n = lines.len();
+    for i in 0..n {
+        for j in i + 2..n {
+            if i == 0 && j == n - 1 {
+                continue; // closing edge is adjacent to the first
+            }
+            if line_intersection(lines[i], lines[j]).is_some() {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Valid rings need 3 distinct points plus the closing one.
+fn is_degenerate(ring: &LineString) -> bool {
+    ring.0.len() < 4
+}
+
+/// Where `c`, a point on `line`, lies along it, 0 at the start and 1 at the end.
+fn fraction(line: Line, c: Coord) -> f64 {
+    let d = line.delta();
+    ((c.x - line.start.x) * d.x + (c.y - line.start.y) * d.y) / (d.x * d.x + d.y * d.y)
+}
+
+/// Splits a self-intersecting ring at its crossings into simple rings, e.g.
+/// a bow tie into its two triangles. `None` where edges overlap or a piece
+/// still crosses itself.
+fn split_ring(ring: &LineString) -> Option<Vec<LineString>> {
+    let lines: Vec<Line> = ring.lines().collect();
+    let n = lines.len();
+    // Crossings on each edge with their place along it.
+    let mut cuts: Vec<Vec<(f64, Coord)>> = vec![vec![]; n];
+    for i in 0..n {
+        for j in i + 2..n {
+            if i == 0 && j == n - 1 {
+                continue;
+            }
+            match line_intersection(lines[i], lines[j]) {
+                Some(LineIntersection::SinglePoint { intersection, .. }) => {
+                    cuts[i].push((fraction(lines[i], intersection), intersection));
+                    cuts[j].push((fraction(lines[j], intersection), intersection));
+                }
+                Some(LineIntersection::Collinear { .. }) => return None,
+                None => {}
+            }
+        }
+    }
+    let mut coords: Vec<Coord> = vec![];
+    for (line, mut cuts) in lines.iter().zip(cuts) {
+        coords.push(line.start);
+        cuts.sort_by(|a, b| a.0.total_cmp(&b.0));
+        coords.extend(cuts.into_iter().map(|(_, c)| c));
+    }
+
+    // Walk the ring and close off a loop each time it gets back to a point.
+    let mut rings: Vec<LineString> = vec![];
+    let mut path: Vec<Coord> = vec![];
+    for c in coords {
+        if let Some(start) = path.iter().position(|p| *p == c) {
+            let mut ring = path.split_off(start);
+            ring.push(c);
+            rings.push(LineString(ring));
+        }
+        path.push(c);
+    }
+    if let Some(first) = path.first().copied() {
+        path.push(first);
+        rings.push(LineString(path));
+    }
+
+    let rings: Vec<LineString> = rings
+        .into_iter()
+        .filter(|r| !is_degenerate(r) && Polygon::new(r.clone(), vec![]).unsigned_area() > 0.)
+        .collect();
+    if rings.is_empty() || rings.iter().any(self_intersects) {
+        return None;
+    }
+    Some(rings)
+}
+
+/// Cleans rings, splits a self-intersecting exterior into simple ones, drops
+/// broken holes and holes outside the exterior, and winds exteriors CCW and
+/// holes CW (in lon/lat), which `Wall` relies on for outward normals. Returns
+/// one polygon per piece, `None` when nothing usable is left.
+pub fn repair_footprint(polygon: Polygon) -> Option<(Vec<Polygon>, Vec<QaFlag>)> {
+    let mut qa: Vec<QaFlag> = vec![];
+    let exterior = clean_ring(polygon.exterior(), &mut qa);
+    if is_degenerate(&exterior) {
+        return None;
+    }
+    let exteriors = if self_intersects(&exterior) {
+        flag(&mut qa, QaFlag::SelfIntersection);
+        split_ring(&exterior).unwrap_or_else(|| vec![exterior])
+    } else {
+        vec![exterior]
+    };
+    let mut polygons: Vec<Polygon> = exteriors
+        .into_iter()
+        .map(|e| Polygon::new(e, vec![]))
+        .collect();
+
+    for interior in polygon.interiors() {
+        let hole = clean_ring(interior, &mut qa);
+        if is_degenerate(&hole) || self_intersects(&hole) {
+            flag(&mut qa, QaFlag::DroppedHole);
+            continue;
+        }
+        match polygons.iter_mut().find(|p| p.contains(&hole)) {
+            Some(polygon) => polygon.interiors_push(hole),
+            None => flag(&mut qa, QaFlag::DroppedHole),
+        }
+    }
+
+    let polygons: Vec<Polygon> = polygons
+        .into_iter()
+        .map(|polygon| {
+            let oriented = polygon.orient(Direction::Default);
+            if oriented != polygon {
+                flag(&mut qa, QaFlag::Reoriented);
+            }
+            oriented
+        })
+        .collect();
+    Some((polygons, qa))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::Winding;
+
+    fn ring(coords: &[(f64, f64)]) -> LineString {
+        coords.iter().map(|&(x, y)| Coord { x, y }).collect()
+    }
+
+    #[test]
+    fn reorients_clockwise_exterior() {
+        let square = ring(&[(0., 0.), (0., 1.), (1., 1.), (1., 0.), (0., 0.)]);
+        let (polygons, qa) = repair_footprint(Polygon::new(square, vec![])).unwrap();
+        assert_eq!(polygons.len(), 1);
+        assert!(polygons[0].exterior().is_ccw());
+        assert_eq!(qa, [QaFlag::Reoriented]);
+    }
+
+    #[test]
+    fn winds_holes_clockwise() {
+        let square = ring(&[(0., 0.), (4., 0.), (4., 4.), (0., 4.), (0., 0.)]);
+        let hole = ring(&[(1., 1.), (3., 1.), (3., 3.), (1., 3.), (1., 1.)]);
+        let (polygons, _) = repair_footprint(Polygon::new(square, vec![hole])).unwrap();
+        assert!(polygons[0].exterior().is_ccw());
+        assert!(polygons[0].interiors()[0].is_cw());
+    }
+
+    #[test]
+    fn drops_duplicate_points() {
+        let doubled = ring(&[(0., 0.), (1., 0.), (1., 0.), (1., 1.), (0., 1.), (0., 0.)]);
+        let (polygons, qa) = repair_footprint(Polygon::new(doubled, vec![])).unwrap();
+        assert_eq!(polygons[0].exterior().0.len(), 5);
+        assert!(polygons[0].exterior().is_closed());
+        assert_eq!(qa, [QaFlag::DuplicatePoints]);
+    }
+
+    #[test]
+    fn keeps_concave_footprint() {
+        let l_shape = ring(&[
+            (0., 0.),
+            (2., 0.),
+            (2., 1.),
+            (1., 1.),
+            (1., 2.),
+            (0., 2.),
+            (0., 0.),
+        ]);
+        let (polygons, qa) = repair_footprint(Polygon::new(l_shape, vec![])).unwrap();
+        assert!(qa.is_empty());
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].unsigned_area(), 3.);
+    }
+
+    #[test]
+    fn splits_bow_tie() {
+        let bow_tie = ring(&[(0., 0.), (2., 2.), (2., 0.), (0., 2.), (0., 0.)]);
+        let (polygons, qa) = repair_footprint(Polygon::new(bow_tie, vec![])).unwrap();
+        assert!(qa.contains(&QaFlag::SelfIntersection));
+        assert_eq!(polygons.len(), 2);
+        for polygon in polygons.iter() {
+            assert_eq!(polygon.unsigned_area(), 1.);
+            assert!(polygon.exterior().is_ccw());
+        }
+    }
+
+    #[test]
+    fn drops_hole_outside_exterior() {
+        let square = ring(&[(0., 0.), (1., 0.), (1., 1.), (0., 1.), (0., 0.)]);
+        let outside = ring(&[(2., 2.), (3., 2.), (3., 3.), (2., 2.)]);
+        let (polygons, qa) = repair_footprint(Polygon::new(square, vec![outside])).unwrap();
+        assert!(polygons[0].interiors().is_empty());
+        assert_eq!(qa, [QaFlag::DroppedHole]);
+    }
+
+    #[test]
+    fn degenerate_is_none() {
+        let line = ring(&[(0., 0.), (1., 0.), (0., 0.)]);
+        assert!(repair_footprint(Polygon::new(line, vec![])).is_none());
+    }
+}
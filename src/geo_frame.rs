@@ -0,0 +1,244 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_egui::{egui, EguiContexts};
+use bevy_panorbit_camera::PanOrbitCamera;
+
+use crate::projection::Projection;
+
+/// Ties the world frame to the map: lon/lat of the origin and the projection
+/// every layer was built with.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct GeoFrame {
+    pub projection: Projection,
+    /// `[lon, lat]` at world 0.
+    pub origin: [f64; 2],
+}
+
+impl GeoFrame {
+    pub fn new(projection: Projection, lon: f64, lat: f64) -> Self {
+        GeoFrame {
+            projection,
+            origin: [lon, lat],
+        }
+    }
+
+    /// On the ground plane.
+    pub fn to_world(&self, lon: f64, lat: f64) -> Vec3 {
+        let [x, z] = self.projection.forward(lon, lat);
+        Vec3::new(x as f32, 0., z as f32)
+    }
+
+    /// `[lon, lat]`, height ignored.
+    pub fn to_lon_lat(&self, position: Vec3) -> [f64; 2] {
+        self.projection
+            .inverse([position.x as f64, position.z as f64])
+    }
+}
+
+/// Lon/lat of the ground under the mouse cursor.
+#[derive(Resource, Default)]
+pub struct CursorLonLat(pub Option<[f64; 2]>);
+
+/// Text of the "Go to" box.
+#[derive(Resource, Default)]
+pub struct GoTo {
+    lon: String,
+    lat: String,
+    error: Option<String>,
+}
+
+pub fn update_cursor_lon_lat(
+    frame: Res<GeoFrame>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<PanOrbitCamera>>,
+    mut cursor: ResMut<CursorLonLat>,
+) {
+    cursor.0 = None;
+    let Some(position) = windows.get_single().ok().and_then(|w| w.cursor_position()) else {
+        return;
+    };
+    let Some((camera, transform)) = cameras.iter().next() else {
+        return;
+    };
+    let Some(ray) = camera.viewport_to_world(transform, position) else {
+        return;
+    };
+    if let Some(distance) = ray.intersect_plane(Vec3::ZERO, Vec3::Y) {
+        cursor.0 = Some(frame.to_lon_lat(ray.get_point(distance)));
+    }
+}
+
+fn format_lon_lat([lon, lat]: [f64; 2]) -> String {
+    format!("{lon:.6}, {lat:.6}")
+}
+
+pub fn status_bar(
+    mut egui: EguiContexts,
+    frame: Res<GeoFrame>,
+    cursor: Res<CursorLonLat>,
+    cameras: Query<&PanOrbitCamera>,
+) {
+    let focus = cameras.iter().next().map(|c| frame.to_lon_lat(c.focus));
+    egui::TopBottomPanel::bottom("status_bar").show(egui.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("lon, lat:");
+            ui.monospace(cursor.0.map_or("-".to_string(), format_lon_lat));
+            ui.separator();
+            ui.label("focus:");
+            if let Some(focus) = focus {
+                let text = format_lon_lat(focus);
+                ui.monospace(&text);
+                if ui.small_button("copy").clicked() {
+                    ui.output_mut(|o| o.copied_text = text);
+                }
+            }
+        });
+    });
+}
+
+pub fn go_to_ui(
+    mut egui: EguiContexts,
+    frame: Res<GeoFrame>,
+    mut go_to: ResMut<GoTo>,
+    mut cameras: Query<&mut PanOrbitCamera>,
+) {
+    let go_to = &mut *go_to;
+    egui::Window::new("Go to")
+        .anchor(egui::Align2::RIGHT_BOTTOM, [-8., -32.])
+        .resizable(false)
+        .show(egui.ctx_mut(), |ui| {
+            egui::Grid::new("go_to").num_columns(2).show(ui, |ui| {
+                ui.label("lon");
+                ui.text_edit_singleline(&mut go_to.lon);
+                ui.end_row();
+                ui.label("lat");
+                ui.text_edit_singleline(&mut go_to.lat);
+                ui.end_row();
+            });
+            let mut target = None;
+            ui.horizontal(|ui| {
+                if ui.button("Go").clicked() {
+                    target = Some(parse_lon_lat(&go_to.lon, &go_to.lat));
+                }
+                if ui.button("Home").clicked() {
+                    target = Some(Ok(frame.origin));
+                }
+            });
+            if let Some(target) = target {
+                go_to.error = match target {
+                    Ok([lon, lat]) => {
+                        // PanOrbitCamera eases `focus` towards the target.
+                        for mut camera in cameras.iter_mut() {
+                            camera.target_focus = frame.to_world(lon, lat);
+                        }
+                        None
+                    }
+                    Err(e) => Some(e),
+                };
+            }
+            if let Some(error) = &go_to.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        });
+}
+
+/// The "Go to" fields. A `lon, lat` pair as copied from the status bar can be
+/// pasted whole into `lon` with `lat` left empty, split at a comma, semicolon
+/// or whitespace.
+fn parse_lon_lat(lon: &str, lat: &str) -> Result<[f64; 2], String> {
+    let pasted = lon
+        .split_once([',', ';'])
+        .or_else(|| lon.trim().split_once(char::is_whitespace));
+    let (lon, lat) = match pasted {
+        Some(pair) if lat.trim().is_empty() => pair,
+        _ => (lon, lat),
+    };
+    let lon: f64 = lon
+        .trim()
+        .parse()
+        .map_err(|_| format!("lon {lon:?} is not a number"))?;
+    let lat: f64 = lat
+        .trim()
+        .parse()
+        .map_err(|_| format!("lat {lat:?} is not a number"))?;
+    if !(-180. ..=180.).contains(&lon) {
+        return Err(format!("lon {lon} not in -180..180"));
+    }
+    if !(-90. ..=90.).contains(&lat) {
+        return Err(format!("lat {lat} not in -90..90"));
+    }
+    Ok([lon, lat])
+}
+
+/// Needs a `GeoFrame` resource.
+pub struct GeoFramePlugin;
+
+impl Plugin for GeoFramePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CursorLonLat>()
+            .init_resource::<GoTo>()
+            .add_systems(
+                Update,
+                (update_cursor_lon_lat, status_bar, go_to_ui).chain(),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lon_then_lat() {
+        assert_eq!(parse_lon_lat("13.4", "52.52"), Ok([13.4, 52.52]));
+        assert_eq!(parse_lon_lat(" -74.0 ", "40.7\n"), Ok([-74.0, 40.7]));
+        // Latitude first puts 95 out of the latitude range.
+        assert!(parse_lon_lat("52.52", "95").is_err());
+    }
+
+    #[test]
+    fn pasted_pairs() {
+        for pair in [
+            "13.4, 52.52",
+            "13.4,52.52",
+            "13.4;52.52",
+            "13.4 52.52",
+            " 13.4\t52.52 ",
+        ] {
+            assert_eq!(parse_lon_lat(pair, ""), Ok([13.4, 52.52]), "{pair:?}");
+        }
+        // The status bar's own format.
+        let copied = format_lon_lat([13.4, 52.52]);
+        assert_eq!(parse_lon_lat(&copied, " "), Ok([13.4, 52.52]));
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert!(parse_lon_lat("180", "90").is_ok());
+        assert!(parse_lon_lat("-180", "-90").is_ok());
+        for (lon, lat) in [
+            ("180.1", "0"),
+            ("-181", "0"),
+            ("0", "90.5"),
+            ("0", "-91"),
+            ("1e400", "0"),
+        ] {
+            assert!(parse_lon_lat(lon, lat).is_err(), "{lon} {lat}");
+        }
+    }
+
+    #[test]
+    fn bad_input() {
+        for (lon, lat) in [
+            ("", ""),
+            ("13.4", ""),
+            ("13.4,", ""),
+            ("east", "52.52"),
+            ("13.4", "north"),
+            ("NaN", "52.52"),
+            ("13.4, 52.52, 10", ""),
+        ] {
+            assert!(parse_lon_lat(lon, lat).is_err(), "{lon:?} {lat:?}");
+        }
+    }
+}
@@ -1,6 +1,10 @@
-use bevy::{pbr::NotShadowCaster, prelude::*};
+use bevy::{pbr::NotShadowCaster, prelude::*, render::mesh::*};
+use strum_macros::EnumIter;
 
 use crate::config::SceneConfig;
+use crate::material::MapMaterialHandle;
+use crate::transportation::RoadSegment;
+use crate::Names;
 
 pub fn plane_start(
     mut cmd: Commands,
@@ -33,3 +37,195 @@ pub fn plane_start(
     //     ..default()
     // });
 }
+
+/// Overture `base` theme types, drawn bottom to top in this order.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BaseType {
+    Land,
+    LandUse,
+    Water,
+}
+
+impl BaseType {
+    /// Height above the ground plane, below the lowest road.
+    pub fn elevation(&self) -> f32 {
+        match self {
+            BaseType::Land => 0.002,
+            BaseType::LandUse => 0.004,
+            BaseType::Water => 0.006,
+        }
+    }
+}
+
+#[derive(EnumIter, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GroundClass {
+    Water,
+    Land,
+    Forest,
+    Grass,
+    Park,
+    Farmland,
+    Wetland,
+    Sand,
+    Rock,
+    Ice,
+    Residential,
+    Commercial,
+    Industrial,
+    Cemetery,
+    Other,
+}
+
+impl GroundClass {
+    /// Keeps water over land use over land where they overlap.
+    pub fn depth_bias(&self) -> f32 {
+        match self {
+            GroundClass::Water => 3.,
+            GroundClass::Land | GroundClass::Other => 1.,
+            _ => 2.,
+        }
+    }
+
+    pub fn from_base(base_type: BaseType, subtype: Option<&str>, class: Option<&str>) -> Self {
+        if base_type == BaseType::Water {
+            return GroundClass::Water;
+        }
+        let class = class.or(subtype).unwrap_or("");
+        match class {
+            "forest" | "wood" | "tree" => GroundClass::Forest,
+            "grass" | "grassland" | "meadow" | "scrub" | "shrub" | "heath" => GroundClass::Grass,
+            "park" | "garden" | "recreation_ground" | "pitch" | "golf_course" | "park_land" => {
+                GroundClass::Park
+            }
+            "farmland" | "farmyard" | "orchard" | "vineyard" | "agriculture" | "allotments" => {
+                GroundClass::Farmland
+            }
+            "wetland" | "marsh" | "swamp" | "bog" => GroundClass::Wetland,
+            "sand" | "beach" | "dune" => GroundClass::Sand,
+            "rock" | "bare_rock" | "scree" | "cliff" | "physical" => GroundClass::Rock,
+            "glacier" | "ice" => GroundClass::Ice,
+            "residential" => GroundClass::Residential,
+            "commercial" | "retail" => GroundClass::Commercial,
+            "industrial" | "railway" | "construction" | "quarry" => GroundClass::Industrial,
+            "cemetery" | "grave_yard" => GroundClass::Cemetery,
+            _ if base_type == BaseType::Land => GroundClass::Land,
+            _ => GroundClass::Other,
+        }
+    }
+}
+
+impl From<&GroundClass> for Color {
+    fn from(class: &GroundClass) -> Self {
+        match class {
+            GroundClass::Water => Color::rgb(0.25, 0.45, 0.7),
+            GroundClass::Land => Color::rgb(0.35, 0.5, 0.32),
+            GroundClass::Forest => Color::rgb(0.15, 0.35, 0.15),
+            GroundClass::Grass => Color::rgb(0.4, 0.6, 0.3),
+            GroundClass::Park => Color::rgb(0.35, 0.6, 0.35),
+            GroundClass::Farmland => Color::rgb(0.6, 0.6, 0.35),
+            GroundClass::Wetland => Color::rgb(0.3, 0.45, 0.4),
+            GroundClass::Sand => Color::rgb(0.85, 0.8, 0.6),
+            GroundClass::Rock => Color::rgb(0.5, 0.5, 0.48),
+            GroundClass::Ice => Color::rgb(0.9, 0.95, 1.),
+            GroundClass::Residential => Color::rgb(0.45, 0.45, 0.4),
+            GroundClass::Commercial => Color::rgb(0.5, 0.45, 0.45),
+            GroundClass::Industrial => Color::rgb(0.45, 0.42, 0.45),
+            GroundClass::Cemetery => Color::rgb(0.3, 0.45, 0.35),
+            GroundClass::Other => Color::rgb(0.4, 0.45, 0.35),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum GroundShape {
+    /// Earcut triangulation, as for building roofs.
+    Polygon {
+        vertices: Vec<[f64; 3]>,
+        triangle_indices: Vec<u32>,
+    },
+    /// Water line (river, stream, canal), drawn as a ribbon.
+    Line(Vec<[f64; 2]>),
+}
+
+/// Flat polygon or line of the `base` theme, drawn on the ground plane.
+///
+/// Every tile a feature's bbox touches loads it, `MapTiles` spawns it from one
+/// of them at a time, so large polygons show while any of these tiles is loaded.
+#[derive(Component, Debug, Clone)]
+pub struct GroundArea {
+    /// Overture feature id, shared by the parts of a multi polygon.
+    pub id: String,
+    pub base_type: BaseType,
+    pub class: GroundClass,
+    pub names: Option<Names>,
+    pub translate: [f64; 2],
+    pub shape: GroundShape,
+}
+
+impl GroundArea {
+    pub fn line_width(&self) -> f32 {
+        match self.class {
+            GroundClass::Water => 8.,
+            _ => 2.,
+        }
+    }
+}
+
+pub fn spawn_ground_area(
+    cmd: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    area: &GroundArea,
+    map_materials: &Res<MapMaterialHandle>,
+) -> Entity {
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    match &area.shape {
+        GroundShape::Polygon {
+            vertices,
+            triangle_indices,
+        } => {
+            let vertices: Vec<[f32; 3]> = vertices.iter().map(|v| v.map(|p| p as f32)).collect();
+            let normals: Vec<[f32; 3]> = vertices.iter().map(|_| [0., 1., 0.]).collect();
+            let uvs: Vec<[f32; 2]> = vertices.iter().map(|p| [p[0], p[2]]).collect();
+            mesh.insert_attribute(
+                Mesh::ATTRIBUTE_POSITION,
+                VertexAttributeValues::from(vertices),
+            );
+            mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, VertexAttributeValues::from(normals));
+            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, VertexAttributeValues::from(uvs));
+            mesh.set_indices(Some(Indices::U32(triangle_indices.clone())));
+        }
+        GroundShape::Line(line) => {
+            let ribbon = RoadSegment::new(line, area.line_width());
+            mesh.insert_attribute(
+                Mesh::ATTRIBUTE_POSITION,
+                VertexAttributeValues::from(ribbon.vertices),
+            );
+            mesh.insert_attribute(
+                Mesh::ATTRIBUTE_NORMAL,
+                VertexAttributeValues::from(ribbon.normals),
+            );
+            mesh.insert_attribute(
+                Mesh::ATTRIBUTE_UV_0,
+                VertexAttributeValues::from(ribbon.uvs),
+            );
+            mesh.set_indices(Some(Indices::U32(ribbon.indices)));
+        }
+    }
+
+    let translate = Vec3::new(
+        area.translate[0] as f32,
+        area.base_type.elevation(),
+        area.translate[1] as f32,
+    );
+    cmd.spawn((
+        PbrBundle {
+            mesh: meshes.add(mesh),
+            material: map_materials.ground.get(&area.class).unwrap().clone(),
+            transform: Transform::from_translation(translate),
+            ..Default::default()
+        },
+        area.clone(),
+        NotShadowCaster,
+    ))
+    .id()
+}
@@ -0,0 +1,322 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::building::{Building, BuildingClass, FLOOR_HEIGHT};
+use crate::map_config::MapConfig;
+
+/// Where a building's `extrusion` height comes from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HeightSource {
+    /// `height` from the data.
+    Measured,
+    /// `num_floors` times the class storey height.
+    Floors,
+    /// Nearby measured buildings, or class and footprint area.
+    #[default]
+    Estimated,
+}
+
+impl HeightSource {
+    pub fn label(&self) -> &'static str {
+        match self {
+            HeightSource::Measured => "measured",
+            HeightSource::Floors => "from floors",
+            HeightSource::Estimated => "estimated",
+        }
+    }
+}
+
+/// Fills in heights for buildings without `height`, run by `poll_tiles` on
+/// the buildings not spawned yet whenever a tile loads. Measured buildings
+/// of all loaded tiles count as neighbours, so a building on a tile border
+/// only misses those of a neighbour tile still loading.
+#[derive(Resource, Debug, Clone)]
+pub struct HeightEstimator {
+    /// Storey height per class in metres, `default_floor_height` for the rest.
+    pub floor_heights: HashMap<BuildingClass, f32>,
+    pub default_floor_height: f32,
+    /// Typical storeys per class when neither height nor floors are known.
+    pub class_floors: HashMap<BuildingClass, f32>,
+    pub default_floors: f32,
+    /// Replaces class storeys times storey height when set.
+    pub fallback_height: Option<f32>,
+    /// Footprints below this many square metres (sheds, garages) get one storey.
+    pub small_footprint: f64,
+    /// Measured buildings within this many metres vote with their median height.
+    pub neighbour_radius: f64,
+    /// Fewer measured neighbours than this fall back to class and footprint.
+    pub min_neighbours: usize,
+}
+
+impl Default for HeightEstimator {
+    fn default() -> Self {
+        use BuildingClass::*;
+        HeightEstimator {
+            floor_heights: [
+                (Commercial, 3.8),
+                (Industrial, 5.),
+                (Agricultural, 4.5),
+                (Education, 3.6),
+                (Medical, 3.6),
+                (Transportation, 5.),
+                (Religious, 5.),
+                (Entertainment, 4.),
+            ]
+            .into_iter()
+            .collect(),
+            default_floor_height: FLOOR_HEIGHT,
+            class_floors: [
+                (Residential, 3.),
+                (Outbuilding, 1.),
+                (Agricultural, 1.),
+                (Commercial, 4.),
+                (Industrial, 2.),
+                (Service, 2.),
+                (Religious, 3.),
+                (Transportation, 2.),
+            ]
+            .into_iter()
+            .collect(),
+            default_floors: 3.,
+            fallback_height: None,
+            small_footprint: 40.,
+            neighbour_radius: 150.,
+            min_neighbours: 3,
+        }
+    }
+}
+
+impl HeightEstimator {
+    /// The defaults, with the storey height, fallback height and neighbour
+    /// radius of `config` where set.
+    pub fn new(config: &MapConfig) -> Self {
+        let defaults = HeightEstimator::default();
+        HeightEstimator {
+            default_floor_height: config.floor_height.unwrap_or(defaults.default_floor_height),
+            fallback_height: config.fallback_height,
+            neighbour_radius: config.neighbour_radius.unwrap_or(defaults.neighbour_radius),
+            ..defaults
+        }
+    }
+
+    /// Indexes the measured ones of `buildings` for `estimate`.
+    pub fn index<'a>(&self, buildings: impl IntoIterator<Item = &'a Building>) -> HeightIndex {
+        HeightIndex::new(buildings, self.neighbour_radius)
+    }
+
+    pub fn floor_height(&self, class: Option<BuildingClass>) -> f32 {
+        class
+            .and_then(|c| self.floor_heights.get(&c).copied())
+            .unwrap_or(self.default_floor_height)
+    }
+
+    /// Sets `height_source`, `floor_height` and, unless measured,
+    /// `estimated_height` on each building, with neighbours from `index`.
+    pub fn estimate(&self, index: &HeightIndex, buildings: &mut [Building]) {
+        for building in buildings.iter_mut() {
+            let floor_height = self.floor_height(building.class);
+            building.floor_height = floor_height;
+            if building.height.is_some() {
+                building.height_source = HeightSource::Measured;
+                continue;
+            }
+            if let Some(floors) = building.num_floors.filter(|f| *f > 0) {
+                building.height_source = HeightSource::Floors;
+                building.estimated_height = Some(floors as f32 * floor_height);
+                continue;
+            }
+            building.height_source = HeightSource::Estimated;
+            building.estimated_height = Some(if footprint_area(building) < self.small_footprint {
+                floor_height
+            } else {
+                let mut heights: Vec<f32> = index
+                    .within(centre(building), self.neighbour_radius)
+                    .collect();
+                if heights.len() >= self.min_neighbours {
+                    heights.sort_by(f32::total_cmp);
+                    heights[heights.len() / 2]
+                } else if let Some(height) = self.fallback_height {
+                    height
+                } else {
+                    let floors = building
+                        .class
+                        .and_then(|c| self.class_floors.get(&c).copied())
+                        .unwrap_or(self.default_floors);
+                    floors * floor_height
+                }
+            });
+        }
+    }
+}
+
+/// World position of the footprint's vertex average.
+fn centre(building: &Building) -> [f64; 2] {
+    let ring = &building.line[..building.line.len().saturating_sub(1)];
+    let n = ring.len().max(1) as f64;
+    let [x, z] = ring
+        .iter()
+        .fold([0., 0.], |c, p| [c[0] + p[0] / n, c[1] + p[1] / n]);
+    [building.translate[0] + x, building.translate[1] + z]
+}
+
+fn ring_area(ring: &[[f64; 2]]) -> f64 {
+    ring.array_windows::<2>()
+        .map(|[p, q]| p[0] * q[1] - q[0] * p[1])
+        .sum::<f64>()
+        .abs()
+        / 2.
+}
+
+/// Square metres, without courtyards.
+pub fn footprint_area(building: &Building) -> f64 {
+    ring_area(&building.line) - building.holes.iter().map(|h| ring_area(h)).sum::<f64>()
+}
+
+/// World centre and height of a measured building.
+type Measured = ([f64; 2], f32);
+
+/// Measured buildings bucketed into `cell`-sized squares.
+pub struct HeightIndex {
+    cell: f64,
+    cells: HashMap<(i64, i64), Vec<Measured>>,
+}
+
+impl HeightIndex {
+    fn new<'a>(buildings: impl IntoIterator<Item = &'a Building>, cell: f64) -> Self {
+        let mut index = HeightIndex {
+            cell,
+            cells: HashMap::new(),
+        };
+        for building in buildings {
+            if let Some(height) = building.height {
+                let p = centre(building);
+                index
+                    .cells
+                    .entry(index.key(p))
+                    .or_default()
+                    .push((p, height as f32));
+            }
+        }
+        index
+    }
+
+    fn key(&self, p: [f64; 2]) -> (i64, i64) {
+        (
+            (p[0] / self.cell).floor() as i64,
+            (p[1] / self.cell).floor() as i64,
+        )
+    }
+
+    /// Heights of measured buildings within `radius` of `p`, `radius` at most `cell`.
+    fn within(&self, p: [f64; 2], radius: f64) -> impl Iterator<Item = f32> + '_ {
+        let (x, z) = self.key(p);
+        (x - 1..=x + 1)
+            .flat_map(move |x| (z - 1..=z + 1).map(move |z| (x, z)))
+            .filter_map(|key| self.cells.get(&key))
+            .flatten()
+            .filter(move |(q, _)| (q[0] - p[0]).hypot(q[1] - p[1]) <= radius)
+            .map(|(_, h)| *h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::building::BuildingGeometryProps;
+    use crate::overture::FeatureMeta;
+
+    /// `size` metres square with its corner at `at`.
+    fn building(at: [f64; 2], size: f64, height: Option<f64>) -> Building {
+        let props = BuildingGeometryProps {
+            translate: at,
+            height,
+            num_floors: None,
+            line: vec![[0., 0.], [size, 0.], [size, size], [0., size], [0., 0.]],
+            holes: vec![],
+            vertices: vec![],
+            triangle_indices: vec![],
+        };
+        Building::from_props(props, None, None, FeatureMeta::default())
+    }
+
+    fn estimate(estimator: &HeightEstimator, buildings: &mut [Building]) {
+        let index = estimator.index(buildings.iter());
+        estimator.estimate(&index, buildings);
+    }
+
+    #[test]
+    fn tags_the_height_source() {
+        let mut floors = building([100., 0.], 10., None);
+        floors.num_floors = Some(4);
+        floors.class = Some(BuildingClass::Commercial);
+        let mut buildings = [
+            building([0., 0.], 10., Some(12.)),
+            floors,
+            building([200., 0.], 10., None),
+        ];
+        estimate(&HeightEstimator::default(), &mut buildings);
+
+        assert_eq!(buildings[0].height_source, HeightSource::Measured);
+        assert_eq!(buildings[0].estimated_height, None);
+        assert_eq!(buildings[1].height_source, HeightSource::Floors);
+        assert_eq!(buildings[1].floor_height, 3.8);
+        assert_eq!(buildings[1].estimated_height, Some(4. * 3.8));
+        assert_eq!(buildings[2].height_source, HeightSource::Estimated);
+        // One measured neighbour, so class storeys.
+        assert_eq!(buildings[2].estimated_height, Some(3. * FLOOR_HEIGHT));
+    }
+
+    #[test]
+    fn median_of_neighbours() {
+        let mut buildings: Vec<Building> = [5., 100., 20., 10., 30.]
+            .into_iter()
+            .enumerate()
+            .map(|(i, h)| building([i as f64 * 20., 50.], 10., Some(h)))
+            .collect();
+        buildings.push(building([0., 0.], 10., None));
+        // Out of `neighbour_radius`.
+        buildings.push(building([1000., 0.], 10., Some(1.)));
+        estimate(&HeightEstimator::default(), &mut buildings);
+        assert_eq!(buildings[5].height_source, HeightSource::Estimated);
+        assert_eq!(buildings[5].estimated_height, Some(20.));
+    }
+
+    #[test]
+    fn too_few_neighbours() {
+        let mut buildings = vec![
+            building([20., 0.], 10., Some(30.)),
+            building([40., 0.], 10., Some(30.)),
+            building([0., 0.], 10., None),
+        ];
+        let mut estimator = HeightEstimator::default();
+        estimate(&estimator, &mut buildings);
+        assert_eq!(buildings[2].estimated_height, Some(3. * FLOOR_HEIGHT));
+
+        estimator.fallback_height = Some(7.);
+        estimate(&estimator, &mut buildings);
+        assert_eq!(buildings[2].estimated_height, Some(7.));
+
+        estimator.min_neighbours = 2;
+        estimate(&estimator, &mut buildings);
+        assert_eq!(buildings[2].estimated_height, Some(30.));
+    }
+
+    #[test]
+    fn small_footprint_is_one_storey() {
+        let mut buildings: Vec<Building> = (0..3)
+            .map(|i| building([i as f64 * 20., 50.], 10., Some(30.)))
+            .collect();
+        // 25 square metres, below `small_footprint`.
+        buildings.push(building([0., 0.], 5., None));
+        estimate(&HeightEstimator::default(), &mut buildings);
+        assert_eq!(buildings[3].estimated_height, Some(FLOOR_HEIGHT));
+    }
+
+    #[test]
+    fn footprint_area_without_courtyards() {
+        let mut courtyard = building([0., 0.], 10., None);
+        assert_eq!(footprint_area(&courtyard), 100.);
+        courtyard.holes = vec![vec![[3., 3.], [3., 7.], [7., 7.], [7., 3.], [3., 3.]]];
+        assert_eq!(footprint_area(&courtyard), 84.);
+    }
+}
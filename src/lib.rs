@@ -0,0 +1,9 @@
+//! The parts shared by the `darkmap` viewer and the `cli` binary: area
+//! config, region manifests, the map projection and SQL quoting. No Bevy in
+//! here.
+
+pub mod geo_util;
+pub mod map_config;
+pub mod projection;
+pub mod regions;
+pub mod sql;
@@ -0,0 +1,178 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use std::sync::Arc;
+
+use crate::data_source::{DuckDbSource, MapDataSource};
+use crate::ground::BaseType;
+use crate::height::HeightEstimator;
+use crate::map_config::{Layer, MapConfig, SourceKind};
+use crate::overture::{update_overture_index, OvertureIndex};
+use crate::parquet_source::ParquetSource;
+use crate::place::PlaceMarkerMesh;
+use crate::projection::Projection;
+use crate::query_base::BaseQueryParams;
+use crate::query_buildings::BuildingsQueryParams;
+use crate::query_places::PlacesQueryParams;
+use crate::query_transportation::TransportationQueryParams;
+use crate::tiles::{poll_tiles, spawn_tiles, update_tiles, MapTiles, TileConfig};
+
+/// How many features get meshed and spawned per frame.
+pub const SPAWN_BATCH: usize = 500;
+
+#[derive(States, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AppState {
+    #[default]
+    Loading,
+    Running,
+}
+
+/// Base queries for the map area, `None` for disabled layers; tiles narrow
+/// them down with a `bbox`.
+#[derive(Resource, Clone)]
+pub struct MapLoadParams {
+    pub source: Arc<dyn MapDataSource>,
+    pub buildings: Option<BuildingsQueryParams>,
+    /// Replace the buildings they belong to.
+    pub building_parts: Option<BuildingsQueryParams>,
+    pub transportation: Option<TransportationQueryParams>,
+    pub places: Option<PlacesQueryParams>,
+    /// One query per enabled `base` theme type.
+    pub base: Vec<BaseQueryParams>,
+}
+
+impl MapLoadParams {
+    /// Queries for the cached layers of the area.
+    pub fn new(map_config: &MapConfig, projection: Projection) -> Self {
+        let path = |layer: Layer| {
+            let path = map_config.parquet_path(layer).display().to_string();
+            debug!("{}: {path}", layer.file_suffix());
+            path
+        };
+        let source: Arc<dyn MapDataSource> = match map_config.source {
+            SourceKind::Duckdb => Arc::new(DuckDbSource::default()),
+            SourceKind::Parquet => Arc::new(ParquetSource),
+        };
+
+        MapLoadParams {
+            source,
+            transportation: map_config.has_layer(Layer::Transportation).then(|| {
+                TransportationQueryParams {
+                    path: path(Layer::Transportation),
+                    bbox: None,
+                    limit: map_config.limit,
+                    projection,
+                }
+            }),
+            places: map_config
+                .has_layer(Layer::Places)
+                .then(|| PlacesQueryParams {
+                    path: path(Layer::Places),
+                    bbox: None,
+                    limit: map_config.limit,
+                    projection,
+                }),
+            base: [
+                (Layer::Land, BaseType::Land),
+                (Layer::LandUse, BaseType::LandUse),
+                (Layer::Water, BaseType::Water),
+            ]
+            .into_iter()
+            .filter(|(layer, _)| map_config.has_layer(*layer))
+            .map(|(layer, base_type)| BaseQueryParams {
+                base_type,
+                path: path(layer),
+                bbox: None,
+                limit: map_config.limit,
+                projection,
+            })
+            .collect(),
+            buildings: map_config
+                .has_layer(Layer::Buildings)
+                .then(|| BuildingsQueryParams {
+                    path: path(Layer::Buildings),
+                    bbox: None,
+                    limit: map_config.limit,
+                    projection,
+                }),
+            building_parts: map_config.has_layer(Layer::BuildingParts).then(|| {
+                BuildingsQueryParams {
+                    path: path(Layer::BuildingParts),
+                    bbox: None,
+                    limit: map_config.limit,
+                    projection,
+                }
+            }),
+        }
+    }
+}
+
+pub struct MapLoadingPlugin;
+
+impl Plugin for MapLoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<AppState>()
+            .init_resource::<TileConfig>()
+            .init_resource::<HeightEstimator>()
+            .init_resource::<MapTiles>()
+            .init_resource::<PlaceMarkerMesh>()
+            .init_resource::<OvertureIndex>()
+            .add_systems(
+                Update,
+                (update_tiles, poll_tiles, spawn_tiles, update_overture_index).chain(),
+            )
+            .add_systems(
+                Update,
+                (finish_map_load, loading_ui)
+                    .after(spawn_tiles)
+                    .run_if(in_state(AppState::Loading)),
+            );
+    }
+}
+
+pub fn finish_map_load(map_tiles: Res<MapTiles>, mut next_state: ResMut<NextState<AppState>>) {
+    if !map_tiles.tiles.is_empty() && map_tiles.is_ready() {
+        next_state.set(AppState::Running);
+    }
+}
+
+pub fn loading_ui(mut egui: EguiContexts, map_tiles: Res<MapTiles>) {
+    let loading = map_tiles
+        .tiles
+        .values()
+        .filter(|t| t.task.is_some())
+        .count();
+    let (spawned, total) = map_tiles
+        .tiles
+        .values()
+        .fold((0, 0), |(spawned, total), t| {
+            (spawned + t.features_spawned(), total + t.features())
+        });
+    let skipped: usize = map_tiles
+        .tiles
+        .values()
+        .map(|t| t.report.skipped.len())
+        .sum();
+
+    egui::Window::new("Loading")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0., 8.))
+        .show(egui.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Loading map");
+            });
+            ui.label(format!(
+                "tiles: {}/{}",
+                map_tiles.tiles.len() - loading,
+                map_tiles.tiles.len()
+            ));
+            ui.label(format!("features: {spawned}/{total}"));
+            if skipped > 0 {
+                ui.label(format!("skipped: {skipped}"));
+            }
+            for error in map_tiles.tiles.values().flat_map(|t| t.errors.iter()) {
+                ui.colored_label(egui::Color32::LIGHT_RED, error);
+            }
+        });
+}
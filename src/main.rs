@@ -2,16 +2,33 @@
 #![feature(array_windows)]
 #![feature(iter_array_chunks)]
 
+mod area_browser;
 mod building;
 mod camera;
 mod config;
-mod geo_util;
+mod data_source;
+mod error;
+mod footprint;
+mod geo_frame;
 mod ground;
+mod height;
 mod light;
+mod loading;
 mod material;
+mod overture;
 mod parquet_import;
+mod parquet_source;
+mod place;
+mod query_base;
 mod query_buildings;
+mod query_places;
 mod query_transportation;
+mod road_graph;
+mod road_properties;
+mod roof;
+mod routing;
+mod selection;
+mod tiles;
 mod transportation;
 
 use bevy::diagnostic::{
@@ -21,17 +38,24 @@ use bevy::diagnostic::{
 use bevy::pbr::DefaultOpaqueRendererMethod;
 use bevy::{pbr::DirectionalLightShadowMap, prelude::*, window::WindowResolution};
 use bevy_egui::egui::{self, Area, FontData, FontDefinitions, FontFamily};
+use darkmap::{geo_util, map_config, projection, regions, sql};
 
 use bevy_egui::{EguiContexts, EguiPlugin};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_mod_picking::pointer::{PointerId, PointerLocation};
 use bevy_mod_picking::{focus::HoverMap, DefaultPickingPlugins};
+use clap::Parser;
 
 use building::*;
-use geo_util::*;
+use footprint::QaFlag;
+use geo_frame::GeoFrame;
+use height::HeightEstimator;
+use loading::*;
+use map_config::{MapArgs, MapConfig};
 use material::*;
-use query_buildings::*;
-use query_transportation::*;
+use place::Place;
+use projection::Projection;
+use selection::road_summary;
 use transportation::*;
 
 pub use geo_types::Coord;
@@ -46,39 +70,41 @@ use crate::{
 #[cfg(feature = "fps")]
 mod dash;
 
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[command(flatten)]
+    map: MapArgs,
+}
+
 fn main() {
     dotenv::dotenv().ok();
 
-    let lat = std::env::var("MAP_LAT").expect("MAP_LAT env");
-    let lat = lat.parse::<f64>().expect("lat to be f64");
-    let lon = std::env::var("MAP_LON").expect("MAP_LON env");
-    let lon = lon.parse::<f64>().expect("lon to be f64");
-    let name = std::env::var("MAP_NAME").expect("MAP_NAME env");
-    let lonlatname = format!("{lon}_{lat}_{name}");
-    println!("{lonlatname}");
-
-    let k = geodesic_to_coord(Coord { x: lon, y: lat });
-    let center_xz: [f64; 2] = [lon * k[0], -lat * k[1]]; // Yto-Z
-
-    let from_transportation =
-        format!("read_parquet('parquet/{lonlatname}_transportation.parquet')");
-    let from_building = format!("read_parquet('parquet/{lonlatname}_building.parquet')");
-    println!("from_transportation:{}", &from_transportation);
-    println!("from_building:{}", &from_building);
-
-    let bevy_transportation = query_transportation(TransportationQueryParams {
-        from_string: from_transportation,
-        limit: None,
-        k,
-        center: center_xz,
-    });
-
-    let bevy_buildings = query_buildings(BuildingsQueryParams {
-        from_string: from_building,
-        limit: None,
-        k,
-        center: center_xz,
-    });
+    let cli = Cli::parse();
+    let mut map_config = match MapConfig::load(&cli.map) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("darkmap: {e}");
+            std::process::exit(2);
+        }
+    };
+    let layers = map_config.layers.clone();
+    match map_config.retain_cached_layers() {
+        Ok(missing) => {
+            for path in missing {
+                eprintln!("darkmap: {} not found, layer disabled", path.display());
+            }
+        }
+        Err(e) => {
+            eprintln!("darkmap: {e}");
+            std::process::exit(2);
+        }
+    }
+    println!("{}", map_config.area_name());
+
+    let projection = Projection::new(map_config.projection, map_config.lon, map_config.lat);
+
+    let map_load_params = MapLoadParams::new(&map_config, projection);
 
     App::new()
         .add_plugins((
@@ -102,34 +128,34 @@ fn main() {
             DefaultPickingPlugins,
             EguiPlugin,
             WorldInspectorPlugin::new(),
+            MapLoadingPlugin,
+            road_graph::RoadGraphPlugin,
+            routing::RoutingPlugin,
+            selection::SelectionPlugin,
+            (geo_frame::GeoFramePlugin, area_browser::AreaBrowserPlugin),
             #[cfg(feature = "fps")]
             crate::dash::DashPlugin,
         ))
         .init_resource::<MapMaterialHandle>()
+        .init_resource::<ColorMaterials>()
         .insert_resource(Msaa::Off)
         .insert_resource(DefaultOpaqueRendererMethod::deferred())
         // .insert_resource(DirectionalLightShadowMap { size: 2048 * 2 })
-        .insert_resource(SceneConfig::default())
-        .insert_resource(Buildings {
-            buildings: bevy_buildings,
+        .insert_resource(SceneConfig {
+            size: map_config.size,
         })
-        .insert_resource(SegmentsRes {
-            segments: bevy_transportation,
+        .insert_resource(map_load_params)
+        .insert_resource(HeightEstimator::new(&map_config))
+        .insert_resource(GeoFrame::new(projection, map_config.lon, map_config.lat))
+        .insert_resource(area_browser::CurrentArea {
+            config: map_config,
+            layers,
         })
         .insert_resource(GizmoConfig {
             depth_bias: -0.5,
             ..default()
         })
-        .add_systems(
-            Startup,
-            (
-                plane_start,
-                light_start_system,
-                buildings_start,
-                transportations_start,
-                install_font,
-            ),
-        )
+        .add_systems(Startup, (plane_start, light_start_system, install_font))
         .add_systems(Update, (animate_light_direction, draw_hover_text))
         .run();
 }
@@ -157,6 +183,7 @@ fn draw_hover_text(
     pointers: Query<(&PointerId, &PointerLocation)>,
     buildings: Query<(&Building, &Transform, &Handle<Mesh>)>,
     roads: Query<(&RoadSegment, &Transform, &Handle<Mesh>)>,
+    places: Query<&Place>,
     meshes: Res<Assets<Mesh>>,
     mut gizmos: Gizmos,
 ) {
@@ -170,6 +197,10 @@ fn draw_hover_text(
         .get(&PointerId::Mouse)
         .and_then(|hits| hits.iter().find_map(|(ent, _)| roads.get(*ent).ok()));
 
+    let place = hovers
+        .get(&PointerId::Mouse)
+        .and_then(|hits| hits.iter().find_map(|(ent, _)| places.get(*ent).ok()));
+
     let pointer = pointers
         .iter()
         .find(|(id, _)| id.is_mouse())
@@ -213,6 +244,20 @@ fn draw_hover_text(
                 .map(|p| transform.transform_point(p)),
             Color::BLUE,
         );
+
+        if let (Some(props), Some(pointer)) = (&road.road, pointer) {
+            egui::show_tooltip_at(
+                ctx,
+                "hover text".into(),
+                Some(egui::Pos2::from(pointer.position.to_array()) + egui::vec2(4., 24.)),
+                |ui| {
+                    for line in road_summary(props) {
+                        ui.label(line);
+                    }
+                    ui.label(format!("length: {:.0} m", road.length()));
+                },
+            );
+        }
     }
 
     if let (Some((building, transform, mesh)), Some(pointer)) = (building, pointer) {
@@ -255,6 +300,54 @@ fn draw_hover_text(
                 if let Some(text) = building.class.as_ref().map(|c| format!("{c:?}")) {
                     ui.label(text);
                 }
+
+                let (min_height, height) = building.extrusion();
+                let source = building.height_source.label();
+                if min_height > 0. {
+                    ui.label(format!("{min_height:.0}-{height:.0} m ({source})"));
+                } else {
+                    ui.label(format!("{height:.0} m ({source})"));
+                }
+                let attrs = &building.attrs;
+                for (key, value) in [
+                    ("roof", &attrs.roof_shape),
+                    ("facade", &attrs.facade_material),
+                    ("roof material", &attrs.roof_material),
+                ] {
+                    if let Some(value) = value {
+                        ui.label(format!("{key}: {value}"));
+                    }
+                }
+
+                ui.weak(&building.meta.id);
+                if !building.qa.is_empty() {
+                    let flags: Vec<&str> = building.qa.iter().map(QaFlag::label).collect();
+                    ui.colored_label(egui::Color32::YELLOW, flags.join(", "));
+                }
+            },
+        );
+    }
+
+    if let (Some(place), Some(pointer)) = (place, pointer) {
+        egui::show_tooltip_at(
+            ctx,
+            "hover text".into(),
+            Some(egui::Pos2::from(pointer.position.to_array()) + egui::vec2(4., 24.)),
+            |ui| {
+                let name = place
+                    .names
+                    .as_ref()
+                    .and_then(|n| n.common_local())
+                    .unwrap_or("");
+                ui.label(format!("{} {name}", place.icon().glyph()));
+
+                if let Some(categories) = &place.categories {
+                    ui.label(&categories.main);
+                }
+
+                if let Some(confidence) = place.confidence {
+                    ui.label(format!("confidence: {confidence:.2}"));
+                }
             },
         );
     }
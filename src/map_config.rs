@@ -0,0 +1,568 @@
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::regions::{Region, RegionManifest, REGIONS_PATH};
+
+// Shared by the `darkmap` viewer and the `cli` binary, see `scene.example.yaml`.
+
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Layer {
+    Buildings,
+    BuildingParts,
+    Transportation,
+    Places,
+    Water,
+    Land,
+    LandUse,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 7] = [
+        Layer::Buildings,
+        Layer::BuildingParts,
+        Layer::Transportation,
+        Layer::Places,
+        Layer::Water,
+        Layer::Land,
+        Layer::LandUse,
+    ];
+
+    /// Suffix of the cached parquet file, `{lon}_{lat}_{name}_{suffix}.parquet`.
+    pub fn file_suffix(&self) -> &'static str {
+        match self {
+            Layer::Buildings => "building",
+            Layer::BuildingParts => "building_part",
+            Layer::Transportation => "transportation",
+            Layer::Places => "place",
+            Layer::Water => "water",
+            Layer::Land => "land",
+            Layer::LandUse => "land_use",
+        }
+    }
+
+    /// Overture `theme=` and `type=` partition the layer is read from.
+    pub fn theme_type(&self) -> (&'static str, &'static str) {
+        match self {
+            Layer::Buildings => ("buildings", "building"),
+            Layer::BuildingParts => ("buildings", "building_part"),
+            Layer::Transportation => ("transportation", "segment"),
+            Layer::Places => ("places", "place"),
+            Layer::Water => ("base", "water"),
+            Layer::Land => ("base", "land"),
+            Layer::LandUse => ("base", "land_use"),
+        }
+    }
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    /// DuckDB, installs its extensions from the network on first run
+    #[default]
+    Duckdb,
+    /// Pure-Rust parquet reader, works offline
+    Parquet,
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectionKind {
+    /// Transverse Mercator centred on the area, metre accurate tens of km out
+    #[default]
+    Local,
+    /// Web Mercator, scaled to metres at the area centre
+    WebMercator,
+    /// UTM zone of the area centre
+    Utm,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct MapArgs {
+    /// Longitude of the area centre
+    pub lon: Option<f64>,
+    /// Latitude of the area centre
+    pub lat: Option<f64>,
+    /// Area name used in cached file names
+    pub name: Option<String>,
+    /// Area of the regions manifest, instead of lon, lat and name
+    #[arg(short, long)]
+    pub region: Option<String>,
+    /// Regions manifest [default: regions.yaml]
+    #[arg(long)]
+    pub regions: Option<PathBuf>,
+    /// YAML scene file, overridden by env vars and arguments
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// Directory with cached parquet files
+    #[arg(long)]
+    pub parquet_dir: Option<PathBuf>,
+    /// Max rows per query
+    #[arg(long)]
+    pub limit: Option<u32>,
+    /// Ground and fog size in metres
+    #[arg(long)]
+    pub size: Option<f32>,
+    /// Enabled layers
+    #[arg(long, value_delimiter = ',')]
+    pub layers: Option<Vec<Layer>>,
+    /// Reader for the cached parquet files
+    #[arg(long)]
+    pub source: Option<SourceKind>,
+    /// Lon/lat to metres projection of the world frame
+    #[arg(long)]
+    pub projection: Option<ProjectionKind>,
+    /// Storey height in metres of building classes without their own
+    #[arg(long)]
+    pub floor_height: Option<f32>,
+    /// Height in metres of buildings without height, floors or measured neighbours
+    #[arg(long)]
+    pub fallback_height: Option<f32>,
+    /// Measured buildings within this many metres set a missing height
+    #[arg(long)]
+    pub neighbour_radius: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct MapConfigFile {
+    lon: Option<f64>,
+    lat: Option<f64>,
+    name: Option<String>,
+    region: Option<String>,
+    regions: Option<PathBuf>,
+    parquet_dir: Option<PathBuf>,
+    limit: Option<u32>,
+    size: Option<f32>,
+    layers: Option<Vec<Layer>>,
+    source: Option<SourceKind>,
+    projection: Option<ProjectionKind>,
+    floor_height: Option<f32>,
+    fallback_height: Option<f32>,
+    neighbour_radius: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MapConfig {
+    pub lon: f64,
+    pub lat: f64,
+    pub name: String,
+    pub parquet_dir: PathBuf,
+    pub limit: Option<u32>,
+    pub size: f32,
+    pub layers: Vec<Layer>,
+    pub source: SourceKind,
+    pub projection: ProjectionKind,
+    /// Manifest entry the area was picked by.
+    pub region: Option<Region>,
+    /// Height estimation settings, the viewer's defaults where `None`.
+    pub floor_height: Option<f32>,
+    pub fallback_height: Option<f32>,
+    pub neighbour_radius: Option<f64>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Yaml(PathBuf, serde_yaml::Error),
+    Missing(&'static str),
+    Invalid(&'static str, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            ConfigError::Yaml(path, e) => write!(f, "{}: {e}", path.display()),
+            ConfigError::Missing(field) => write!(
+                f,
+                "{field} is not set, pass it as an argument, in --config or as MAP_{} env",
+                field.to_uppercase()
+            ),
+            ConfigError::Invalid(field, reason) => write!(f, "invalid {field}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn env_var<T: std::str::FromStr>(
+    key: &'static str,
+    field: &'static str,
+) -> Result<Option<T>, ConfigError> {
+    match std::env::var(key) {
+        Ok(v) if !v.is_empty() => v
+            .parse()
+            .map(Some)
+            .map_err(|_| ConfigError::Invalid(field, format!("{key}={v:?}"))),
+        _ => Ok(None),
+    }
+}
+
+/// Lon, lat and name of a `MapConfig::area_name`.
+pub fn parse_area_name(area: &str) -> Option<(f64, f64, &str)> {
+    let mut parts = area.splitn(3, '_');
+    let lon = parts.next()?.parse().ok()?;
+    let lat = parts.next()?.parse().ok()?;
+    let name = parts.next().filter(|n| !n.is_empty())?;
+    Some((lon, lat, name))
+}
+
+impl MapConfig {
+    /// Merges the scene file, `MAP_*` env vars and arguments, later ones
+    /// winning. A picked region sets lon, lat and name on its own.
+    pub fn load(args: &MapArgs) -> Result<Self, ConfigError> {
+        let file: MapConfigFile = match &args.config {
+            Some(path) => {
+                let f = std::fs::File::open(path).map_err(|e| ConfigError::Io(path.clone(), e))?;
+                serde_yaml::from_reader(f).map_err(|e| ConfigError::Yaml(path.clone(), e))?
+            }
+            None => MapConfigFile::default(),
+        };
+
+        let mut manifest_dir = None;
+        let region = match args.region.clone().or(file.region) {
+            Some(name) => {
+                let path = args
+                    .regions
+                    .clone()
+                    .or(file.regions)
+                    .unwrap_or_else(|| PathBuf::from(REGIONS_PATH));
+                let manifest = RegionManifest::load(&path)?;
+                manifest_dir = manifest.parquet_dir.clone();
+                Some(manifest.get(&name)?.clone())
+            }
+            None => None,
+        };
+        // A region names the area itself, `MAP_*` from `.env` must not move it.
+        let (lon, lat, name) = match &region {
+            Some(region) => {
+                if let Some(key) = [
+                    args.lon.map(|_| "lon"),
+                    args.lat.map(|_| "lat"),
+                    args.name.as_ref().map(|_| "name"),
+                ]
+                .into_iter()
+                .flatten()
+                .next()
+                {
+                    return Err(ConfigError::Invalid(
+                        key,
+                        format!("cannot be given with region {:?}", region.name),
+                    ));
+                }
+                // `RegionManifest::load` checked the centre.
+                let [lon, lat] = region.centre().ok_or(ConfigError::Missing("lon"))?;
+                (lon, lat, region.name.clone())
+            }
+            None => (
+                args.lon
+                    .or(env_var("MAP_LON", "lon")?)
+                    .or(file.lon)
+                    .ok_or(ConfigError::Missing("lon"))?,
+                args.lat
+                    .or(env_var("MAP_LAT", "lat")?)
+                    .or(file.lat)
+                    .ok_or(ConfigError::Missing("lat"))?,
+                args.name
+                    .clone()
+                    .or(env_var("MAP_NAME", "name")?)
+                    .or(file.name)
+                    .ok_or(ConfigError::Missing("name"))?,
+            ),
+        };
+
+        let config = MapConfig {
+            lon,
+            lat,
+            name,
+            parquet_dir: args
+                .parquet_dir
+                .clone()
+                .or(manifest_dir)
+                .or(file.parquet_dir)
+                .unwrap_or_else(|| PathBuf::from("parquet")),
+            limit: args.limit.or(file.limit),
+            size: args.size.or(file.size).unwrap_or(20000.),
+            layers: args
+                .layers
+                .clone()
+                .or(region.as_ref().and_then(|r| r.layers.clone()))
+                .or(file.layers)
+                .unwrap_or_else(|| Layer::ALL.to_vec()),
+            source: args.source.or(file.source).unwrap_or_default(),
+            projection: args.projection.or(file.projection).unwrap_or_default(),
+            region,
+            floor_height: args.floor_height.or(file.floor_height),
+            fallback_height: args.fallback_height.or(file.fallback_height),
+            neighbour_radius: args.neighbour_radius.or(file.neighbour_radius),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(-180. ..=180.).contains(&self.lon) {
+            return Err(ConfigError::Invalid(
+                "lon",
+                format!("{} not in -180..180", self.lon),
+            ));
+        }
+        if !(-90. ..=90.).contains(&self.lat) {
+            return Err(ConfigError::Invalid(
+                "lat",
+                format!("{} not in -90..90", self.lat),
+            ));
+        }
+        if self.name.is_empty() || self.name.contains(['/', '\\', '\'']) {
+            return Err(ConfigError::Invalid(
+                "name",
+                format!("{:?} must be non-empty without / \\ or '", self.name),
+            ));
+        }
+        if !self.size.is_finite() || self.size <= 0. {
+            return Err(ConfigError::Invalid(
+                "size",
+                format!("{} must be > 0", self.size),
+            ));
+        }
+        if self.limit == Some(0) {
+            return Err(ConfigError::Invalid("limit", "must be > 0".to_string()));
+        }
+        for (field, value) in [
+            ("floor_height", self.floor_height.map(f64::from)),
+            ("fallback_height", self.fallback_height.map(f64::from)),
+            ("neighbour_radius", self.neighbour_radius),
+        ] {
+            if let Some(value) = value.filter(|v| !v.is_finite() || *v <= 0.) {
+                return Err(ConfigError::Invalid(field, format!("{value} must be > 0")));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn area_name(&self) -> String {
+        format!("{}_{}_{}", self.lon, self.lat, self.name)
+    }
+
+    pub fn parquet_path(&self, layer: Layer) -> PathBuf {
+        self.parquet_dir.join(format!(
+            "{}_{}.parquet",
+            self.area_name(),
+            layer.file_suffix()
+        ))
+    }
+
+    /// Row counts and checksums written by `cli cache-all`.
+    pub fn record_path(&self) -> PathBuf {
+        self.parquet_dir
+            .join(format!("{}_cache.yaml", self.area_name()))
+    }
+
+    pub fn has_layer(&self, layer: Layer) -> bool {
+        self.layers.contains(&layer)
+    }
+
+    /// Drops enabled layers whose parquet file is not cached, returning their
+    /// paths; fails if nothing is left to show.
+    pub fn retain_cached_layers(&mut self) -> Result<Vec<PathBuf>, ConfigError> {
+        let mut missing = vec![];
+        let layers = std::mem::take(&mut self.layers);
+        for layer in layers {
+            let path = self.parquet_path(layer);
+            if path.is_file() {
+                self.layers.push(layer);
+            } else {
+                missing.push(path);
+            }
+        }
+        if self.layers.is_empty() {
+            return Err(ConfigError::Invalid(
+                "parquet_dir",
+                format!(
+                    "no cached layers for {} in {}, cache it with `cli location`",
+                    self.area_name(),
+                    self.parquet_dir.display()
+                ),
+            ));
+        }
+        Ok(missing)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::{Mutex, MutexGuard};
+
+    /// `MAP_*` env vars are process wide, tests loading a config hold this.
+    static ENV: Mutex<()> = Mutex::new(());
+
+    pub(crate) fn lock_env() -> MutexGuard<'static, ()> {
+        let guard = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        for key in ["MAP_LON", "MAP_LAT", "MAP_NAME"] {
+            std::env::remove_var(key);
+        }
+        guard
+    }
+
+    /// Writes `contents` to `name` in a temp dir of this test run.
+    pub(crate) fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("darkmap-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn config() -> MapConfig {
+        MapConfig {
+            lon: 13.4,
+            lat: 52.52,
+            name: "berlin".to_string(),
+            parquet_dir: PathBuf::from("parquet"),
+            limit: None,
+            size: 20000.,
+            layers: Layer::ALL.to_vec(),
+            source: SourceKind::default(),
+            projection: ProjectionKind::default(),
+            region: None,
+            floor_height: None,
+            fallback_height: None,
+            neighbour_radius: None,
+        }
+    }
+
+    fn invalid_field(result: Result<(), ConfigError>) -> &'static str {
+        match result {
+            Err(ConfigError::Invalid(field, _)) => field,
+            other => panic!("expected Invalid, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn args_over_env_over_file() {
+        let _env = lock_env();
+        let file = temp_file(
+            "merge.yaml",
+            "lon: 1.0\nlat: 2.0\nname: file\nlimit: 10\nsize: 500\nlayers: [buildings]\n",
+        );
+        std::env::set_var("MAP_LAT", "3.0");
+        std::env::set_var("MAP_NAME", "env");
+        let args = MapArgs {
+            config: Some(file),
+            name: Some("args".to_string()),
+            limit: Some(5),
+            ..Default::default()
+        };
+        let config = MapConfig::load(&args).unwrap();
+        assert_eq!(config.lon, 1.0);
+        assert_eq!(config.lat, 3.0);
+        assert_eq!(config.name, "args");
+        assert_eq!(config.limit, Some(5));
+        assert_eq!(config.size, 500.);
+        assert_eq!(config.layers, [Layer::Buildings]);
+        assert_eq!(config.parquet_dir, PathBuf::from("parquet"));
+    }
+
+    #[test]
+    fn missing_lon() {
+        let _env = lock_env();
+        let args = MapArgs {
+            lat: Some(52.52),
+            name: Some("berlin".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            MapConfig::load(&args),
+            Err(ConfigError::Missing("lon"))
+        ));
+    }
+
+    #[test]
+    fn unparsable_env() {
+        let _env = lock_env();
+        std::env::set_var("MAP_LON", "east");
+        let args = MapArgs {
+            lat: Some(52.52),
+            name: Some("berlin".to_string()),
+            ..Default::default()
+        };
+        let result = MapConfig::load(&args).map(|_| ());
+        std::env::remove_var("MAP_LON");
+        assert_eq!(invalid_field(result), "lon");
+    }
+
+    #[test]
+    fn validates_ranges() {
+        assert!(config().validate().is_ok());
+        type Breaks = fn(&mut MapConfig);
+        let cases: [(&str, Breaks); 10] = [
+            ("lon", |c| c.lon = 180.5),
+            ("lat", |c| c.lat = -90.5),
+            ("name", |c| c.name = String::new()),
+            ("name", |c| c.name = "../berlin".to_string()),
+            ("name", |c| c.name = "it's".to_string()),
+            ("size", |c| c.size = 0.),
+            ("size", |c| c.size = f32::NAN),
+            ("limit", |c| c.limit = Some(0)),
+            ("floor_height", |c| c.floor_height = Some(-3.)),
+            ("neighbour_radius", |c| {
+                c.neighbour_radius = Some(f64::INFINITY)
+            }),
+        ];
+        for (field, break_config) in cases {
+            let mut config = config();
+            break_config(&mut config);
+            assert_eq!(invalid_field(config.validate()), field);
+        }
+    }
+
+    #[test]
+    fn region_excludes_lon_lat_and_name() {
+        let _env = lock_env();
+        let regions = temp_file(
+            "exclusive-regions.yaml",
+            "regions:\n  - name: berlin\n    lon: 13.4\n    lat: 52.52\n",
+        );
+        for args in [
+            MapArgs {
+                lon: Some(2.35),
+                ..Default::default()
+            },
+            MapArgs {
+                lat: Some(48.85),
+                ..Default::default()
+            },
+            MapArgs {
+                name: Some("paris".to_string()),
+                ..Default::default()
+            },
+        ] {
+            let args = MapArgs {
+                region: Some("berlin".to_string()),
+                regions: Some(regions.clone()),
+                ..args
+            };
+            let result = MapConfig::load(&args).map(|_| ());
+            assert!(["lon", "lat", "name"].contains(&invalid_field(result)));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let _env = lock_env();
+        let file = temp_file("unknown.yaml", "lon: 1.0\nlat: 2.0\nname: file\nzoom: 3\n");
+        let args = MapArgs {
+            config: Some(file),
+            ..Default::default()
+        };
+        let result = MapConfig::load(&args);
+        assert!(
+            matches!(&result, Err(ConfigError::Yaml(_, e)) if e.to_string().contains("zoom")),
+            "{result:?}"
+        );
+    }
+}
@@ -1,7 +1,11 @@
-use bevy::prelude::{default, Assets, Color, FromWorld, Handle, Resource, StandardMaterial, World};
+use bevy::prelude::{
+    default, AlphaMode, Assets, Color, FromWorld, Handle, Resource, StandardMaterial, World,
+};
 use std::collections::HashMap;
 use strum::IntoEnumIterator;
 
+use crate::ground::GroundClass;
+use crate::place::PlaceIcon;
 use crate::{BuildingClass, RoadClass};
 
 type Reflectance = f32;
@@ -28,6 +32,12 @@ pub struct MapMaterialHandle {
     pub unknown_building: Handle<StandardMaterial>,
     pub unknown_building_roof: Handle<StandardMaterial>,
     pub road: HashMap<RoadClass, Handle<StandardMaterial>>,
+    /// Translucent, for segments below ground level.
+    pub road_tunnel: HashMap<RoadClass, Handle<StandardMaterial>>,
+    pub place: HashMap<PlaceIcon, Handle<StandardMaterial>>,
+    pub ground: HashMap<GroundClass, Handle<StandardMaterial>>,
+    /// Route overlay, drawn over all roads.
+    pub route: Handle<StandardMaterial>,
 }
 
 impl FromWorld for MapMaterialHandle {
@@ -134,6 +144,65 @@ impl FromWorld for MapMaterialHandle {
                 .or_insert_with_key(|_key| road_color_handle);
         }
 
+        let mut road_tunnel: HashMap<RoadClass, Handle<StandardMaterial>> = HashMap::new();
+        for road_class in RoadClass::iter() {
+            let handle = standard_materials.add(StandardMaterial {
+                base_color: Color::from(&road_class).with_a(0.3),
+                alpha_mode: AlphaMode::Blend,
+                depth_bias: road_class.depth_bias() * 100.,
+                reflectance: 0.1,
+                perceptual_roughness: 0.9,
+                ..default()
+            });
+            road_tunnel.insert(road_class, handle);
+        }
+
+        let mut place: HashMap<PlaceIcon, Handle<StandardMaterial>> = HashMap::new();
+        for icon in PlaceIcon::iter() {
+            let color = Color::from(&icon);
+            let place_color_handle = standard_materials.add(StandardMaterial {
+                base_color: color,
+                emissive: color * 0.3,
+                reflectance: 0.5,
+                perceptual_roughness: 0.5,
+                ..default()
+            });
+            place
+                .entry(icon)
+                .or_insert_with_key(|_key| place_color_handle);
+        }
+
+        let mut ground: HashMap<GroundClass, Handle<StandardMaterial>> = HashMap::new();
+        for ground_class in GroundClass::iter() {
+            let color = Color::from(&ground_class);
+            let ground_color_handle = standard_materials.add(StandardMaterial {
+                base_color: color,
+                depth_bias: ground_class.depth_bias(),
+                reflectance: if ground_class == GroundClass::Water {
+                    0.8
+                } else {
+                    0.3
+                },
+                perceptual_roughness: if ground_class == GroundClass::Water {
+                    0.2
+                } else {
+                    0.9
+                },
+                ..default()
+            });
+            ground
+                .entry(ground_class)
+                .or_insert_with_key(|_key| ground_color_handle);
+        }
+
+        let route = standard_materials.add(StandardMaterial {
+            base_color: Color::CYAN,
+            emissive: Color::CYAN * 0.5,
+            depth_bias: 2000.,
+            unlit: true,
+            ..default()
+        });
+
         Self {
             roof,
             roofs,
@@ -141,6 +210,69 @@ impl FromWorld for MapMaterialHandle {
             unknown_building,
             unknown_building_roof,
             road,
+            road_tunnel,
+            place,
+            ground,
+            route,
         }
     }
 }
+
+/// Materials for colours given by the data (`facade_color`, `roof_color`),
+/// shared by every building with the same colour.
+#[derive(Resource, Default)]
+pub struct ColorMaterials {
+    handles: HashMap<[u8; 4], Handle<StandardMaterial>>,
+}
+
+impl ColorMaterials {
+    pub fn get_or_add(
+        &mut self,
+        color: Color,
+        standard_materials: &mut Assets<StandardMaterial>,
+    ) -> Handle<StandardMaterial> {
+        self.handles
+            .entry(color.as_rgba_u8())
+            .or_insert_with(|| {
+                let (reflectance, roughness) = DEFAULT_MATERIAL_PROPS;
+                standard_materials.add(StandardMaterial {
+                    base_color: color,
+                    reflectance,
+                    perceptual_roughness: roughness,
+                    ..default()
+                })
+            })
+            .clone()
+    }
+}
+
+/// `#rrggbb`, `#rgb` (with or without `#`) or a common CSS colour name, as
+/// Overture colour fields come.
+pub fn parse_color(s: &str) -> Option<Color> {
+    let s = s.trim();
+    if let Ok(color) = Color::hex(s.strip_prefix('#').unwrap_or(s)) {
+        return Some(color);
+    }
+    Some(match s.to_lowercase().as_str() {
+        "white" => Color::WHITE,
+        "black" => Color::BLACK,
+        "gray" | "grey" => Color::GRAY,
+        "silver" => Color::SILVER,
+        "red" => Color::RED,
+        "maroon" => Color::MAROON,
+        "orange" => Color::ORANGE,
+        "yellow" => Color::YELLOW,
+        "olive" => Color::OLIVE,
+        "green" => Color::GREEN,
+        "lime" => Color::LIME_GREEN,
+        "teal" => Color::TEAL,
+        "blue" => Color::BLUE,
+        "navy" => Color::NAVY,
+        "purple" => Color::PURPLE,
+        "pink" => Color::PINK,
+        "beige" => Color::BEIGE,
+        "brown" => Color::rgb(0.6, 0.4, 0.2),
+        "tan" => Color::rgb(0.82, 0.71, 0.55),
+        _ => return None,
+    })
+}
@@ -0,0 +1,113 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::building::Building;
+use crate::error::MapLoadError;
+use crate::transportation::RoadSegment;
+
+// https://docs.overturemaps.org/reference/buildings/building (sources, updateTime)
+
+/// Overture feature GUID.
+pub type OvertureId = String;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Source {
+    /// JSON pointer to the property the source is for, empty for the whole feature.
+    pub property: Option<String>,
+    pub dataset: String,
+    #[serde(alias = "recordid")]
+    pub record_id: Option<String>,
+    pub confidence: Option<f64>,
+}
+
+/// Where a feature comes from, enough to report it upstream.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureMeta {
+    pub id: OvertureId,
+    pub sources: Vec<Source>,
+    pub update_time: Option<String>,
+}
+
+impl FeatureMeta {
+    /// `sources` as JSON.
+    pub fn new(
+        id: OvertureId,
+        sources: Option<String>,
+        update_time: Option<String>,
+    ) -> Result<Self, MapLoadError> {
+        let sources: Vec<Source> = sources
+            .map(|s| serde_json::from_str(&s))
+            .transpose()?
+            .unwrap_or_default();
+        Ok(FeatureMeta {
+            id,
+            sources,
+            update_time,
+        })
+    }
+
+    /// Highest confidence any source gives.
+    pub fn confidence(&self) -> Option<f64> {
+        self.sources
+            .iter()
+            .filter_map(|s| s.confidence)
+            .max_by(f64::total_cmp)
+    }
+}
+
+/// Spawned entities per Overture id; a multipolygon building spawns walls
+/// and roof for each part.
+#[derive(Resource, Default)]
+pub struct OvertureIndex {
+    entities: HashMap<OvertureId, Vec<Entity>>,
+    ids: HashMap<Entity, OvertureId>,
+}
+
+impl OvertureIndex {
+    pub fn get(&self, id: &str) -> &[Entity] {
+        self.entities.get(id).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn id(&self, entity: Entity) -> Option<&OvertureId> {
+        self.ids.get(&entity)
+    }
+
+    fn insert(&mut self, id: &OvertureId, entity: Entity) {
+        self.entities.entry(id.clone()).or_default().push(entity);
+        self.ids.insert(entity, id.clone());
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        let Some(id) = self.ids.remove(&entity) else {
+            return;
+        };
+        if let Some(entities) = self.entities.get_mut(&id) {
+            entities.retain(|e| *e != entity);
+            if entities.is_empty() {
+                self.entities.remove(&id);
+            }
+        }
+    }
+}
+
+pub fn update_overture_index(
+    mut index: ResMut<OvertureIndex>,
+    buildings: Query<(Entity, &Building), Added<Building>>,
+    roads: Query<(Entity, &RoadSegment), Added<RoadSegment>>,
+    mut removed_buildings: RemovedComponents<Building>,
+    mut removed_roads: RemovedComponents<RoadSegment>,
+) {
+    for entity in removed_buildings.read().chain(removed_roads.read()) {
+        index.remove(entity);
+    }
+    for (entity, building) in buildings.iter() {
+        index.insert(&building.meta.id, entity);
+    }
+    for (entity, road) in roads.iter() {
+        if let Some(meta) = &road.meta {
+            index.insert(&meta.id, entity);
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use parquet::file::metadata::RowGroupMetaData;
+use parquet::file::serialized_reader::{ReadOptionsBuilder, SerializedFileReader};
+use parquet::file::statistics::Statistics;
+use parquet::record::{Field, Row};
+use std::fs::File;
+
+use crate::building::{Building, BuildingAttrs};
+use crate::data_source::{bbox_contains_center, bbox_intersects, MapDataSource};
+use crate::error::{LoadReport, MapLoadError};
+use crate::ground::GroundArea;
+use crate::place::Place;
+use crate::query_base::{BaseQueryParams, BaseRow};
+use crate::query_buildings::{BuildingRow, BuildingsQueryParams};
+use crate::query_places::{PlaceRow, PlacesQueryParams};
+use crate::query_transportation::{SegmentRow, TransportationQueryParams};
+use crate::transportation::Segment;
+
+/// Reads cached parquet files with the `parquet` crate only, no DuckDB
+/// extensions or network needed. Row groups whose `bbox` statistics put
+/// every feature outside the queried bbox are not read.
+pub struct ParquetSource;
+
+/// How the feature bbox of a row is matched against the queried bbox, as the
+/// DuckDB queries do.
+#[derive(Debug, Clone, Copy)]
+enum BboxFilter {
+    /// `bbox_contains_center`, a feature is loaded by one tile.
+    Centre,
+    /// `bbox_intersects`, a `base` feature is loaded by every tile it touches.
+    Intersects,
+}
+
+impl BboxFilter {
+    fn matches(self, bbox: [f64; 4], feature_bbox: [f64; 4]) -> bool {
+        match self {
+            BboxFilter::Centre => bbox_contains_center(bbox, feature_bbox),
+            BboxFilter::Intersects => bbox_intersects(bbox, feature_bbox),
+        }
+    }
+
+    /// `[x_min, y_min, x_max, y_max]` the matched points of the features of a
+    /// row group lie in, their centres or their whole bboxes. `None` without
+    /// `bbox.*` min/max statistics.
+    fn row_group_bounds(self, row_group: &RowGroupMetaData) -> Option<[f64; 4]> {
+        let [min_x, min_y, max_x, max_y] = row_group_ranges(row_group)?;
+        Some(match self {
+            BboxFilter::Centre => [
+                (min_x.0 + max_x.0) / 2.,
+                (min_y.0 + max_y.0) / 2.,
+                (min_x.1 + max_x.1) / 2.,
+                (min_y.1 + max_y.1) / 2.,
+            ],
+            BboxFilter::Intersects => [min_x.0, min_y.0, max_x.1, max_y.1],
+        })
+    }
+}
+
+/// (min, max) of the `bbox` minX, minY, maxX and maxY columns of a row group.
+fn row_group_ranges(row_group: &RowGroupMetaData) -> Option<[(f64, f64); 4]> {
+    // (min, max) of minX, minY, maxX, maxY
+    let mut ranges = [None; 4];
+    for column in row_group.columns() {
+        let [parent, name] = column.column_path().parts() else {
+            continue;
+        };
+        if parent != "bbox" {
+            continue;
+        }
+        let i = match name.as_str() {
+            "minX" | "xmin" => 0,
+            "minY" | "ymin" => 1,
+            "maxX" | "xmax" => 2,
+            "maxY" | "ymax" => 3,
+            _ => continue,
+        };
+        ranges[i] = match column.statistics()? {
+            Statistics::Double(s) if s.has_min_max_set() => Some((*s.min(), *s.max())),
+            Statistics::Float(s) if s.has_min_max_set() => Some((*s.min() as f64, *s.max() as f64)),
+            _ => None,
+        };
+    }
+    let [Some(min_x), Some(min_y), Some(max_x), Some(max_y)] = ranges else {
+        return None;
+    };
+    Some([min_x, min_y, max_x, max_y])
+}
+
+/// Rows of the row groups that may hold a feature matching `bbox`.
+fn rows(
+    path: &str,
+    bbox: Option<[f64; 4]>,
+    filter: BboxFilter,
+) -> Result<impl Iterator<Item = parquet::errors::Result<Row>>, MapLoadError> {
+    let mut options = ReadOptionsBuilder::new();
+    if let Some(bbox) = bbox {
+        options = options.with_predicate(Box::new(move |row_group, _| {
+            filter
+                .row_group_bounds(row_group)
+                .map_or(true, |b| bbox_intersects(bbox, b))
+        }));
+    }
+    let reader = SerializedFileReader::new_with_options(File::open(path)?, options.build())?;
+    Ok(reader.into_iter())
+}
+
+/// Reads the rows matching `bbox` and turns each into features with
+/// `build`, which can record skipped parts of a row in the report. A row that
+/// fails to build is skipped and recorded too, `limit` counts rows that gave
+/// at least one feature.
+fn scan<R>(
+    path: &str,
+    bbox: Option<[f64; 4]>,
+    limit: Option<u32>,
+    build: impl Fn(&RowFields, &mut LoadReport) -> Result<Vec<R>, MapLoadError>,
+) -> Result<(Vec<R>, LoadReport), MapLoadError> {
+    scan_matching(path, bbox, BboxFilter::Centre, limit, build)
+}
+
+/// `scan` with another `BboxFilter`.
+fn scan_matching<R>(
+    path: &str,
+    bbox: Option<[f64; 4]>,
+    filter: BboxFilter,
+    limit: Option<u32>,
+    build: impl Fn(&RowFields, &mut LoadReport) -> Result<Vec<R>, MapLoadError>,
+) -> Result<(Vec<R>, LoadReport), MapLoadError> {
+    let mut features: Vec<R> = vec![];
+    let mut report = LoadReport::default();
+    for row in rows(path, bbox, filter)? {
+        if limit.is_some_and(|l| report.loaded >= l as usize) {
+            break;
+        }
+        let row = match row {
+            Ok(row) => row,
+            Err(e) => {
+                report.skip(None, e.into());
+                continue;
+            }
+        };
+        let fields = RowFields::new(&row);
+        let built = match fields.in_bbox(bbox, filter) {
+            Ok(true) => build(&fields, &mut report),
+            Ok(false) => Ok(vec![]),
+            Err(e) => Err(e),
+        };
+        match built {
+            Ok(built) if built.is_empty() => {}
+            Ok(built) => {
+                features.extend(built);
+                report.loaded += 1;
+            }
+            Err(e) => report.skip(fields.optional("id", as_string).as_deref(), e),
+        }
+    }
+    Ok((features, report))
+}
+
+fn as_string(field: &Field) -> Option<String> {
+    match field {
+        Field::Str(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn as_f64(field: &Field) -> Option<f64> {
+    match field {
+        Field::Double(v) => Some(*v),
+        Field::Float(v) => Some(*v as f64),
+        Field::Int(v) => Some(*v as f64),
+        Field::Long(v) => Some(*v as f64),
+        _ => None,
+    }
+}
+
+fn as_i32(field: &Field) -> Option<i32> {
+    match field {
+        Field::Int(v) => Some(*v),
+        Field::Long(v) => i32::try_from(*v).ok(),
+        Field::Short(v) => Some(*v as i32),
+        _ => None,
+    }
+}
+
+/// Strings are passed through, nested values converted like DuckDB's `JSON()`.
+fn as_json(field: &Field) -> Option<String> {
+    match field {
+        Field::Null => None,
+        Field::Str(s) => Some(s.clone()),
+        f => Some(f.to_json_value().to_string()),
+    }
+}
+
+fn as_bbox(field: &Field) -> Option<[f64; 4]> {
+    let Field::Group(row) = field else {
+        return None;
+    };
+    let mut bbox = [None; 4];
+    for (name, field) in row.get_column_iter() {
+        let i = match name.as_str() {
+            "minX" | "xmin" => 0,
+            "minY" | "ymin" => 1,
+            "maxX" | "xmax" => 2,
+            "maxY" | "ymax" => 3,
+            _ => continue,
+        };
+        bbox[i] = as_f64(field);
+    }
+    Some([bbox[0]?, bbox[1]?, bbox[2]?, bbox[3]?])
+}
+
+/// Columns of one row, looked up by name.
+struct RowFields<'a> {
+    fields: Vec<(&'a String, &'a Field)>,
+}
+
+impl<'a> RowFields<'a> {
+    fn new(row: &'a Row) -> Self {
+        RowFields {
+            fields: row.get_column_iter().collect(),
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&'a Field> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n.as_str() == name)
+            .map(|(_, f)| *f)
+    }
+
+    fn required<T>(
+        &self,
+        name: &'static str,
+        convert: impl Fn(&Field) -> Option<T>,
+    ) -> Result<T, MapLoadError> {
+        self.get(name)
+            .and_then(convert)
+            .ok_or_else(|| MapLoadError::Schema(format!("missing or mistyped column {name}")))
+    }
+
+    fn optional<T>(&self, name: &str, convert: impl Fn(&Field) -> Option<T>) -> Option<T> {
+        self.get(name).and_then(convert)
+    }
+
+    /// `updateTime`, `updatetime` in some releases.
+    fn update_time(&self) -> Option<String> {
+        self.get("updateTime")
+            .or_else(|| self.get("updatetime"))
+            .map(|f| match f {
+                Field::Str(s) => s.clone(),
+                f => f.to_string(),
+            })
+    }
+
+    fn in_bbox(&self, bbox: Option<[f64; 4]>, filter: BboxFilter) -> Result<bool, MapLoadError> {
+        match bbox {
+            Some(bbox) => Ok(filter.matches(bbox, self.required("bbox", as_bbox)?)),
+            None => Ok(true),
+        }
+    }
+
+    fn geometry(&self) -> Result<Vec<u8>, MapLoadError> {
+        self.required("geometry", |f| match f {
+            Field::Bytes(b) => Some(b.data().to_vec()),
+            _ => None,
+        })
+    }
+}
+
+impl MapDataSource for ParquetSource {
+    fn buildings(
+        &self,
+        params: BuildingsQueryParams,
+    ) -> Result<(Vec<Building>, LoadReport), MapLoadError> {
+        scan(&params.path, params.bbox, params.limit, |fields, report| {
+            BuildingRow {
+                id: fields.required("id", as_string)?,
+                height: fields.optional("height", as_f64),
+                names: fields.optional("names", as_json),
+                geom: fields.geometry()?,
+                num_floors: fields.optional("numFloors", as_i32),
+                class: fields.optional("class", as_string),
+                sources: fields.optional("sources", as_json),
+                update_time: fields.update_time(),
+                attrs: BuildingAttrs {
+                    min_height: fields.optional("minHeight", as_f64),
+                    min_floor: fields.optional("minFloor", as_i32),
+                    roof_shape: fields.optional("roofShape", as_string),
+                    roof_height: fields.optional("roofHeight", as_f64),
+                    roof_direction: fields.optional("roofDirection", as_f64),
+                    facade_color: fields.optional("facadeColor", as_string),
+                    facade_material: fields.optional("facadeMaterial", as_string),
+                    roof_color: fields.optional("roofColor", as_string),
+                    roof_material: fields.optional("roofMaterial", as_string),
+                    building_id: fields.optional("buildingId", as_string),
+                },
+            }
+            .into_buildings(params.projection, report)
+        })
+    }
+
+    /// Same columns as `building`, missing ones just read as `None`.
+    fn building_parts(
+        &self,
+        params: BuildingsQueryParams,
+    ) -> Result<(Vec<Building>, LoadReport), MapLoadError> {
+        self.buildings(params)
+    }
+
+    fn segments(
+        &self,
+        params: TransportationQueryParams,
+    ) -> Result<(Vec<Segment>, LoadReport), MapLoadError> {
+        scan(&params.path, params.bbox, params.limit, |fields, _| {
+            let segment = SegmentRow {
+                id: fields.required("id", as_string)?,
+                geom: fields.geometry()?,
+                road: fields.optional("road", as_json),
+                level: fields.optional("level", as_i32),
+                connectors: fields.optional("connectors", as_json),
+                width: fields.optional("width", as_f64),
+                sources: fields.optional("sources", as_json),
+                update_time: fields.update_time(),
+            }
+            .into_segment(params.projection)?;
+            Ok(segment.into_iter().collect())
+        })
+    }
+
+    fn places(&self, params: PlacesQueryParams) -> Result<(Vec<Place>, LoadReport), MapLoadError> {
+        scan(&params.path, params.bbox, params.limit, |fields, _| {
+            let place = PlaceRow {
+                id: fields.required("id", as_string)?,
+                names: fields.optional("names", as_json),
+                categories: fields.optional("categories", as_json),
+                confidence: fields.optional("confidence", as_f64),
+                geom: fields.geometry()?,
+            }
+            .into_place(params.projection)?;
+            Ok(vec![place])
+        })
+    }
+
+    fn base(&self, params: BaseQueryParams) -> Result<(Vec<GroundArea>, LoadReport), MapLoadError> {
+        scan_matching(
+            &params.path,
+            params.bbox,
+            BboxFilter::Intersects,
+            params.limit,
+            |fields, _| {
+                BaseRow {
+                    id: fields.required("id", as_string)?,
+                    subtype: fields.optional("subtype", as_string),
+                    class: fields.optional("class", as_string),
+                    names: fields.optional("names", as_json),
+                    geom: fields.geometry()?,
+                }
+                .into_areas(params.base_type, params.projection)
+            },
+        )
+    }
+}
@@ -0,0 +1,157 @@
+use bevy::{pbr::NotShadowCaster, prelude::*};
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use crate::material::MapMaterialHandle;
+use crate::Names;
+
+/// Marker top above the ground, in metres.
+const MARKER_HEIGHT: f32 = 6.;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Categories {
+    pub main: String,
+    pub alternate: Option<Vec<String>>,
+}
+
+// https://docs.overturemaps.org/reference/places/place
+#[derive(Component, Debug, Clone)]
+pub struct Place {
+    pub names: Option<Names>,
+    pub categories: Option<Categories>,
+    pub confidence: Option<f64>,
+    pub translate: [f64; 2],
+}
+
+impl Place {
+    pub fn icon(&self) -> PlaceIcon {
+        self.categories
+            .as_ref()
+            .map(|c| PlaceIcon::from_category(&c.main))
+            .unwrap_or_default()
+    }
+}
+
+/// Coarse grouping of the Overture place categories, one marker colour and icon each.
+#[derive(Default, EnumIter, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PlaceIcon {
+    Food,
+    Shopping,
+    Lodging,
+    Health,
+    Education,
+    Transport,
+    Leisure,
+    Services,
+    #[default]
+    Other,
+}
+
+impl PlaceIcon {
+    /// Matches whole words of the snake_case category, e.g. `coffee_shop`.
+    pub fn from_category(category: &str) -> PlaceIcon {
+        let has = |words: &[&str]| category.split('_').any(|part| words.contains(&part));
+        if has(&[
+            "restaurant",
+            "cafe",
+            "coffee",
+            "bar",
+            "pub",
+            "bakery",
+            "food",
+        ]) {
+            PlaceIcon::Food
+        } else if has(&["shop", "shopping", "store", "market", "mall", "boutique"]) {
+            PlaceIcon::Shopping
+        } else if has(&["hotel", "hostel", "motel", "lodging", "accommodation"]) {
+            PlaceIcon::Lodging
+        } else if has(&[
+            "hospital", "clinic", "doctor", "dentist", "pharmacy", "health",
+        ]) {
+            PlaceIcon::Health
+        } else if has(&["school", "college", "university", "education", "library"]) {
+            PlaceIcon::Education
+        } else if has(&["station", "airport", "parking", "bus", "train", "transport"]) {
+            PlaceIcon::Transport
+        } else if has(&["park", "museum", "theatre", "cinema", "gym", "sport"]) {
+            PlaceIcon::Leisure
+        } else if has(&["bank", "service", "services", "office", "agency", "repair"]) {
+            PlaceIcon::Services
+        } else {
+            PlaceIcon::Other
+        }
+    }
+
+    pub fn glyph(&self) -> &'static str {
+        match self {
+            PlaceIcon::Food => "🍴",
+            PlaceIcon::Shopping => "🛒",
+            PlaceIcon::Lodging => "🛏",
+            PlaceIcon::Health => "⚕",
+            PlaceIcon::Education => "🎓",
+            PlaceIcon::Transport => "🚉",
+            PlaceIcon::Leisure => "🎭",
+            PlaceIcon::Services => "🔧",
+            PlaceIcon::Other => "📍",
+        }
+    }
+}
+
+impl From<&PlaceIcon> for Color {
+    fn from(icon: &PlaceIcon) -> Self {
+        match icon {
+            PlaceIcon::Food => Color::ORANGE,
+            PlaceIcon::Shopping => Color::FUCHSIA,
+            PlaceIcon::Lodging => Color::MIDNIGHT_BLUE,
+            PlaceIcon::Health => Color::RED,
+            PlaceIcon::Education => Color::GOLD,
+            PlaceIcon::Transport => Color::BLUE,
+            PlaceIcon::Leisure => Color::LIME_GREEN,
+            PlaceIcon::Services => Color::TEAL,
+            PlaceIcon::Other => Color::WHITE,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct PlaceMarkerMesh(pub Handle<Mesh>);
+
+impl FromWorld for PlaceMarkerMesh {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        PlaceMarkerMesh(
+            meshes.add(
+                shape::Capsule {
+                    radius: 1.,
+                    depth: MARKER_HEIGHT - 2.,
+                    ..default()
+                }
+                .into(),
+            ),
+        )
+    }
+}
+
+pub fn spawn_place(
+    cmd: &mut Commands,
+    marker: &Res<PlaceMarkerMesh>,
+    place: &Place,
+    map_materials: &Res<MapMaterialHandle>,
+) -> Entity {
+    let translate = Vec3::new(
+        place.translate[0] as f32,
+        MARKER_HEIGHT / 2.,
+        place.translate[1] as f32,
+    );
+    cmd.spawn((
+        PbrBundle {
+            mesh: marker.0.clone(),
+            material: map_materials.place.get(&place.icon()).unwrap().clone(),
+            transform: Transform::from_translation(translate),
+            ..Default::default()
+        },
+        place.clone(),
+        NotShadowCaster,
+    ))
+    .id()
+}
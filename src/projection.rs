@@ -0,0 +1,307 @@
+use std::f64::consts::{FRAC_PI_4, PI};
+
+use crate::map_config::ProjectionKind;
+
+// WGS84
+const A: f64 = 6_378_137.;
+const F: f64 = 1. / 298.257_223_563;
+const UTM_SCALE: f64 = 0.9996;
+const UTM_FALSE_EASTING: f64 = 500_000.;
+const UTM_FALSE_NORTHING_SOUTH: f64 = 10_000_000.;
+
+/// Ellipsoidal transverse Mercator, series from Snyder, "Map Projections:
+/// A Working Manual" (1987), pp. 60-64; millimetre accurate a few degrees
+/// either side of `lon0`.
+#[derive(Debug, Clone, Copy)]
+pub struct TransverseMercator {
+    /// Central meridian, degrees.
+    lon0: f64,
+    /// Scale on the central meridian.
+    k0: f64,
+    false_easting: f64,
+    false_northing: f64,
+    /// Meridian distance to the latitude of origin.
+    m0: f64,
+}
+
+impl TransverseMercator {
+    pub fn new(lon0: f64, lat0: f64, k0: f64, false_easting: f64, false_northing: f64) -> Self {
+        TransverseMercator {
+            lon0,
+            k0,
+            false_easting,
+            false_northing,
+            m0: meridian_distance(lat0.to_radians()),
+        }
+    }
+
+    /// Easting and northing in metres.
+    pub fn forward(&self, lon: f64, lat: f64) -> [f64; 2] {
+        let (e2, ep2) = eccentricity();
+        let phi = lat.to_radians();
+        let (sin, cos, tan) = (phi.sin(), phi.cos(), phi.tan());
+        let n = A / (1. - e2 * sin * sin).sqrt();
+        let t = tan * tan;
+        let c = ep2 * cos * cos;
+        let a = (lon - self.lon0).to_radians() * cos;
+
+        let x = self.k0
+            * n
+            * (a + (1. - t + c) * a.powi(3) / 6.
+                + (5. - 18. * t + t * t + 72. * c - 58. * ep2) * a.powi(5) / 120.);
+        let y = self.k0
+            * (meridian_distance(phi) - self.m0
+                + n * tan
+                    * (a * a / 2.
+                        + (5. - t + 9. * c + 4. * c * c) * a.powi(4) / 24.
+                        + (61. - 58. * t + t * t + 600. * c - 330. * ep2) * a.powi(6) / 720.));
+        [x + self.false_easting, y + self.false_northing]
+    }
+
+    /// `[lon, lat]` in degrees.
+    pub fn inverse(&self, easting: f64, northing: f64) -> [f64; 2] {
+        let (e2, ep2) = eccentricity();
+        let x = easting - self.false_easting;
+        let y = northing - self.false_northing;
+
+        let m = self.m0 + y / self.k0;
+        let mu = m / (A * (1. - e2 / 4. - 3. * e2 * e2 / 64. - 5. * e2.powi(3) / 256.));
+        let e1 = (1. - (1. - e2).sqrt()) / (1. + (1. - e2).sqrt());
+        let phi1 = mu
+            + (3. * e1 / 2. - 27. * e1.powi(3) / 32.) * (2. * mu).sin()
+            + (21. * e1 * e1 / 16. - 55. * e1.powi(4) / 32.) * (4. * mu).sin()
+            + (151. * e1.powi(3) / 96.) * (6. * mu).sin()
+            + (1097. * e1.powi(4) / 512.) * (8. * mu).sin();
+
+        let (sin, cos, tan) = (phi1.sin(), phi1.cos(), phi1.tan());
+        let c1 = ep2 * cos * cos;
+        let t1 = tan * tan;
+        let n1 = A / (1. - e2 * sin * sin).sqrt();
+        let r1 = A * (1. - e2) / (1. - e2 * sin * sin).powf(1.5);
+        let d = x / (n1 * self.k0);
+
+        let phi = phi1
+            - (n1 * tan / r1)
+                * (d * d / 2.
+                    - (5. + 3. * t1 + 10. * c1 - 4. * c1 * c1 - 9. * ep2) * d.powi(4) / 24.
+                    + (61. + 90. * t1 + 298. * c1 + 45. * t1 * t1 - 252. * ep2 - 3. * c1 * c1)
+                        * d.powi(6)
+                        / 720.);
+        let lambda = (d - (1. + 2. * t1 + c1) * d.powi(3) / 6.
+            + (5. - 2. * c1 + 28. * t1 - 3. * c1 * c1 + 8. * ep2 + 24. * t1 * t1) * d.powi(5)
+                / 120.)
+            / cos;
+        [self.lon0 + lambda.to_degrees(), phi.to_degrees()]
+    }
+}
+
+/// `e²` and `e'²`.
+fn eccentricity() -> (f64, f64) {
+    let e2 = F * (2. - F);
+    (e2, e2 / (1. - e2))
+}
+
+fn meridian_distance(phi: f64) -> f64 {
+    let (e2, _) = eccentricity();
+    let (e4, e6) = (e2 * e2, e2 * e2 * e2);
+    A * ((1. - e2 / 4. - 3. * e4 / 64. - 5. * e6 / 256.) * phi
+        - (3. * e2 / 8. + 3. * e4 / 32. + 45. * e6 / 1024.) * (2. * phi).sin()
+        + (15. * e4 / 256. + 45. * e6 / 1024.) * (4. * phi).sin()
+        - (35. * e6 / 3072.) * (6. * phi).sin())
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Plane {
+    TransverseMercator(TransverseMercator),
+    /// Spherical, scaled so a unit is a metre at the origin's latitude.
+    WebMercator {
+        scale: f64,
+    },
+}
+
+impl Plane {
+    fn forward(&self, lon: f64, lat: f64) -> [f64; 2] {
+        match self {
+            Plane::TransverseMercator(tm) => tm.forward(lon, lat),
+            Plane::WebMercator { scale } => [
+                scale * A * lon.to_radians(),
+                scale * A * (FRAC_PI_4 + lat.to_radians() / 2.).tan().ln(),
+            ],
+        }
+    }
+
+    fn inverse(&self, x: f64, y: f64) -> [f64; 2] {
+        match self {
+            Plane::TransverseMercator(tm) => tm.inverse(x, y),
+            Plane::WebMercator { scale } => [
+                (x / (scale * A)).to_degrees(),
+                (2. * (y / (scale * A)).exp().atan() - PI / 2.).to_degrees(),
+            ],
+        }
+    }
+}
+
+/// Maps lon/lat to the world frame every layer is built in: metres with the
+/// map origin at 0, `x` east and `z` south.
+#[derive(Debug, Clone, Copy)]
+pub struct Projection {
+    plane: Plane,
+    /// Projected origin, subtracted so the origin lands on 0.
+    offset: [f64; 2],
+}
+
+impl Projection {
+    pub fn new(kind: ProjectionKind, lon: f64, lat: f64) -> Self {
+        let plane = match kind {
+            ProjectionKind::Local => {
+                Plane::TransverseMercator(TransverseMercator::new(lon, lat, 1., 0., 0.))
+            }
+            ProjectionKind::Utm => {
+                let zone = utm_zone(lon);
+                let false_northing = if lat < 0. {
+                    UTM_FALSE_NORTHING_SOUTH
+                } else {
+                    0.
+                };
+                Plane::TransverseMercator(TransverseMercator::new(
+                    zone as f64 * 6. - 183.,
+                    0.,
+                    UTM_SCALE,
+                    UTM_FALSE_EASTING,
+                    false_northing,
+                ))
+            }
+            ProjectionKind::WebMercator => Plane::WebMercator {
+                scale: lat.to_radians().cos(),
+            },
+        };
+        Projection {
+            plane,
+            offset: plane.forward(lon, lat),
+        }
+    }
+
+    /// World `[x, z]` of a lon/lat.
+    pub fn forward(&self, lon: f64, lat: f64) -> [f64; 2] {
+        let [x, y] = self.plane.forward(lon, lat);
+        [x - self.offset[0], -(y - self.offset[1])] // Yto-Z
+    }
+
+    /// `[lon, lat]` of a world `[x, z]`.
+    pub fn inverse(&self, xz: [f64; 2]) -> [f64; 2] {
+        self.plane
+            .inverse(xz[0] + self.offset[0], -xz[1] + self.offset[1]) // Yto-Z
+    }
+}
+
+/// 1..=60, ignoring the Norway and Svalbard exceptions.
+pub fn utm_zone(lon: f64) -> u8 {
+    (((lon + 180.) / 6.).floor() as i32).rem_euclid(60) as u8 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [ProjectionKind; 3] = [
+        ProjectionKind::Local,
+        ProjectionKind::Utm,
+        ProjectionKind::WebMercator,
+    ];
+
+    fn assert_close(a: [f64; 2], b: [f64; 2], tolerance: f64) {
+        assert!(
+            (a[0] - b[0]).abs() < tolerance && (a[1] - b[1]).abs() < tolerance,
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn origin_is_zero() {
+        for kind in KINDS {
+            for (lon, lat) in [(13.4, 52.52), (-73.98, 40.75), (151.21, -33.87)] {
+                let projection = Projection::new(kind, lon, lat);
+                assert_close(projection.forward(lon, lat), [0., 0.], 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for kind in KINDS {
+            for (lon, lat) in [(13.4, 52.52), (-73.98, 40.75), (151.21, -33.87), (0., 0.)] {
+                let projection = Projection::new(kind, lon, lat);
+                for [dx, dz] in [
+                    [0., 0.],
+                    [20_000., 0.],
+                    [-15_000., 12_000.],
+                    [3_000., -20_000.],
+                ] {
+                    let [lon2, lat2] = projection.inverse([dx, dz]);
+                    assert_close(projection.forward(lon2, lat2), [dx, dz], 1e-3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn x_east_z_south() {
+        for kind in KINDS {
+            let projection = Projection::new(kind, 13.4, 52.52);
+            let [x, z] = projection.forward(13.41, 52.53);
+            assert!(x > 0. && z < 0., "{kind:?}: {x}, {z}");
+        }
+    }
+
+    #[test]
+    fn local_is_metric_near_the_origin() {
+        // 0.01° of latitude north of 52.52° is 1112.77 m of meridian arc.
+        let projection = Projection::new(ProjectionKind::Local, 13.4, 52.52);
+        let [x, z] = projection.forward(13.4, 52.53);
+        assert!(x.abs() < 1e-6);
+        assert!((z + 1112.77).abs() < 0.01, "{z}");
+    }
+
+    #[test]
+    fn quarter_meridian() {
+        // WGS84 equator to pole, 10 001 965.729 m.
+        assert!((meridian_distance(PI / 2.) - 10_001_965.729).abs() < 0.05);
+    }
+
+    #[test]
+    fn utm_known_points() {
+        let tm = TransverseMercator::new(-75., 0., UTM_SCALE, UTM_FALSE_EASTING, 0.);
+        // Central meridian: false easting, k0 times the meridian arc.
+        assert_close(tm.forward(-75., 0.), [500_000., 0.], 1e-6);
+        let [easting, northing] = tm.forward(-75., 45.);
+        assert!((easting - 500_000.).abs() < 1e-6);
+        assert!((northing - UTM_SCALE * meridian_distance(45f64.to_radians())).abs() < 1e-6);
+        // Symmetric about the central meridian.
+        let east = tm.forward(-72., 40.);
+        let west = tm.forward(-78., 40.);
+        assert_close(
+            [east[0] - 500_000., east[1]],
+            [500_000. - west[0], west[1]],
+            1e-6,
+        );
+
+        assert_eq!(utm_zone(-73.98), 18);
+        assert_eq!(utm_zone(13.4), 33);
+        assert_eq!(utm_zone(-180.), 1);
+        assert_eq!(utm_zone(180.), 1);
+    }
+
+    #[test]
+    fn web_mercator_known_points() {
+        // Scale 1 at the equator, the world is 2π·A wide.
+        let projection = Projection::new(ProjectionKind::WebMercator, 0., 0.);
+        let half_world = PI * A;
+        assert_close(projection.forward(180., 0.), [half_world, 0.], 1e-6);
+        assert_close(
+            projection.forward(0., 85.051_128_779_806_6),
+            [0., -half_world],
+            1e-3,
+        );
+        assert_close(projection.inverse([half_world, 0.]), [180., 0.], 1e-9);
+    }
+}
@@ -0,0 +1,157 @@
+use duckdb::Connection;
+use geo_types::{Geometry, LineString, Polygon};
+use geozero::wkb::FromWkb;
+use geozero::wkb::WkbDialect;
+
+use crate::building::polygon_building;
+use crate::data_source::bbox_intersects_where;
+use crate::error::{LoadReport, MapLoadError};
+use crate::geo_util::geometry_type;
+use crate::ground::{BaseType, GroundArea, GroundClass, GroundShape};
+use crate::projection::Projection;
+use crate::sql::sql_string;
+use crate::transportation::line_string_road;
+use crate::Names;
+
+// https://docs.overturemaps.org/reference/base/water
+// https://docs.overturemaps.org/reference/base/land
+// https://docs.overturemaps.org/reference/base/land-use
+
+#[derive(Clone)]
+pub struct BaseQueryParams {
+    pub base_type: BaseType,
+    /// Parquet file, or anything `read_parquet` accepts for the DuckDB source.
+    pub path: String,
+    /// `[lon_min, lat_min, lon_max, lat_max]`, see `data_source::bbox_intersects_where`.
+    pub bbox: Option<[f64; 4]>,
+    pub limit: Option<u32>,
+    pub projection: Projection,
+}
+
+/// Base theme columns as read by any `MapDataSource`.
+#[derive(Debug)]
+pub struct BaseRow {
+    pub id: String,
+    pub subtype: Option<String>,
+    pub class: Option<String>,
+    /// `names` as JSON.
+    pub names: Option<String>,
+    /// WKB.
+    pub geom: Vec<u8>,
+}
+
+impl BaseRow {
+    pub fn into_areas(
+        self,
+        base_type: BaseType,
+        projection: Projection,
+    ) -> Result<Vec<GroundArea>, MapLoadError> {
+        let mut rdr = std::io::Cursor::new(self.geom);
+        let geometry = Geometry::from_wkb(&mut rdr, WkbDialect::Wkb)?;
+        let names: Option<Names> = self.names.map(|n| serde_json::from_str(&n)).transpose()?;
+        let class =
+            GroundClass::from_base(base_type, self.subtype.as_deref(), self.class.as_deref());
+
+        let (polygons, lines): (Vec<Polygon>, Vec<LineString>) = match geometry {
+            Geometry::MultiPolygon(multy_polygon) => (multy_polygon.0, vec![]),
+            Geometry::Polygon(polygon) => (vec![polygon], vec![]),
+            // Rivers and streams come as lines, drawn as ribbons like roads.
+            Geometry::LineString(line_string) if base_type == BaseType::Water => {
+                (vec![], vec![line_string])
+            }
+            Geometry::MultiLineString(lines) if base_type == BaseType::Water => (vec![], lines.0),
+            other => {
+                return Err(MapLoadError::UnsupportedGeometry(geometry_type(&other)));
+            }
+        };
+
+        let mut areas: Vec<GroundArea> = vec![];
+        for polygon in polygons {
+            if polygon.exterior().0.len() < 4 {
+                return Err(MapLoadError::UnsupportedGeometry("degenerate Polygon"));
+            }
+            let props = polygon_building(polygon, projection, None, None);
+            areas.push(GroundArea {
+                id: self.id.clone(),
+                base_type,
+                class,
+                names: names.clone(),
+                translate: props.translate,
+                shape: GroundShape::Polygon {
+                    vertices: props.vertices,
+                    triangle_indices: props.triangle_indices,
+                },
+            });
+        }
+        for line_string in lines {
+            if line_string.0.len() < 2 {
+                return Err(MapLoadError::UnsupportedGeometry(
+                    "LineString with less than 2 points",
+                ));
+            }
+            let (translate, line) = line_string_road(line_string, projection);
+            areas.push(GroundArea {
+                id: self.id.clone(),
+                base_type,
+                class,
+                names: names.clone(),
+                translate,
+                shape: GroundShape::Line(line),
+            });
+        }
+        Ok(areas)
+    }
+}
+
+pub fn query_base(
+    conn: &Connection,
+    params: BaseQueryParams,
+) -> Result<(Vec<GroundArea>, LoadReport), MapLoadError> {
+    let from = format!("read_parquet({})", sql_string(&params.path));
+    let where_string: String = match params.bbox {
+        Some(bbox) => format!("WHERE {}", bbox_intersects_where(bbox)),
+        None => String::from(""),
+    };
+    let limit: String = match params.limit {
+        Some(l) => format!("LIMIT {}", l),
+        None => String::from(""),
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id,
+                subtype,
+                class,
+                JSON(names) as names,
+                geometry,
+            FROM {from} {where_string} {limit}"
+    ))?;
+    let query_iter = stmt.query_map([], |row| {
+        Ok(BaseRow {
+            id: row.get(0)?,
+            subtype: row.get(1)?,
+            class: row.get(2)?,
+            names: row.get(3)?,
+            geom: row.get(4)?,
+        })
+    })?;
+
+    let mut areas: Vec<GroundArea> = vec![];
+    let mut report = LoadReport::default();
+    for query_item in query_iter {
+        let query_item = match query_item {
+            Ok(item) => item,
+            Err(e) => {
+                report.skip(None, e.into());
+                continue;
+            }
+        };
+        let id = query_item.id.clone();
+        match query_item.into_areas(params.base_type, params.projection) {
+            Ok(a) => {
+                areas.extend(a);
+                report.loaded += 1;
+            }
+            Err(e) => report.skip(Some(&id), e),
+        }
+    }
+    Ok((areas, report))
+}
@@ -1,159 +1,211 @@
-use std::str::FromStr;
-
 use duckdb::Connection;
-use geo_types::Geometry;
+use geo_types::{Geometry, Polygon};
 use geozero::wkb::FromWkb;
 use geozero::wkb::WkbDialect;
 
-use crate::building::{polygon_building, Building};
-use crate::BuildingClass;
-use crate::KxyGeodesic;
-use crate::Names;
+use crate::building::{polygon_building, Building, BuildingAttrs, BuildingClass, Names};
+use crate::data_source::bbox_where;
+use crate::error::{LoadReport, MapLoadError};
+use crate::footprint::repair_footprint;
+use crate::geo_util::geometry_type;
+use crate::overture::FeatureMeta;
+use crate::projection::Projection;
+use crate::sql::sql_string;
 
 // https://github.com/OvertureMaps/data/issues/8 duckdb issue
 // https://bertt.wordpress.com/2023/07/31/overture-maps/
 // https://github.com/shi-works/Overture-Maps-Data-for-GIS // japan
 
+#[derive(Clone)]
 pub struct BuildingsQueryParams {
-    pub from_string: String,
+    /// Parquet file, or anything `read_parquet` accepts for the DuckDB source.
+    pub path: String,
+    /// `[lon_min, lat_min, lon_max, lat_max]`, see `data_source::bbox_where`.
+    pub bbox: Option<[f64; 4]>,
     pub limit: Option<u32>,
-    pub k: KxyGeodesic,
-    pub center: [f64; 2],
+    pub projection: Projection,
+}
+
+/// Building columns as read by any `MapDataSource`.
+#[derive(Debug)]
+pub struct BuildingRow {
+    pub id: String,
+    pub height: Option<f64>,
+    /// `names` as JSON.
+    pub names: Option<String>,
+    /// WKB.
+    pub geom: Vec<u8>,
+    pub num_floors: Option<i32>,
+    pub class: Option<String>,
+    /// `sources` as JSON.
+    pub sources: Option<String>,
+    pub update_time: Option<String>,
+    pub attrs: BuildingAttrs,
+}
+
+impl BuildingRow {
+    /// One building per usable polygon. Degenerate parts of a MultiPolygon
+    /// are recorded in `report`, the feature fails only if none is left.
+    pub fn into_buildings(
+        self,
+        projection: Projection,
+        report: &mut LoadReport,
+    ) -> Result<Vec<Building>, MapLoadError> {
+        let meta = FeatureMeta::new(self.id, self.sources, self.update_time)?;
+        let id = &meta.id;
+        let mut rdr = std::io::Cursor::new(self.geom);
+        let polygons: Vec<Polygon> = match Geometry::from_wkb(&mut rdr, WkbDialect::Wkb)? {
+            Geometry::MultiPolygon(multy_polygon) => multy_polygon.0,
+            Geometry::Polygon(polygon) => vec![polygon],
+            not_polygon => {
+                return Err(MapLoadError::UnsupportedGeometry(geometry_type(
+                    &not_polygon,
+                )))
+            }
+        };
+
+        let building_class: Option<BuildingClass> =
+            self.class.map(|c| c.parse().unwrap_or_default());
+        let names: Option<Names> = self.names.map(|n| serde_json::from_str(&n)).transpose()?;
+
+        let mut buildings: Vec<Building> = vec![];
+        let mut degenerate: Vec<usize> = vec![];
+        for (part, polygon) in polygons.into_iter().enumerate() {
+            let Some((polygons, qa)) = repair_footprint(polygon) else {
+                degenerate.push(part);
+                continue;
+            };
+            for polygon in polygons {
+                let building = polygon_building(polygon, projection, self.height, self.num_floors);
+                let mut building =
+                    Building::from_props(building, building_class, names.clone(), meta.clone());
+                building.qa = qa.clone();
+                building.attrs = self.attrs.clone();
+                buildings.push(building);
+            }
+        }
+        if buildings.is_empty() {
+            return Err(MapLoadError::UnsupportedGeometry("degenerate Polygon"));
+        }
+        for part in degenerate {
+            report.skip(
+                Some(&format!("{id} part {part}")),
+                MapLoadError::UnsupportedGeometry("degenerate Polygon"),
+            );
+        }
+        Ok(buildings)
+    }
+}
+
+/// Columns shared by `building` and `building_part`, after `class`.
+const ATTRS_COLUMNS: &str = "minHeight,
+                minFloor,
+                roofShape,
+                roofHeight,
+                roofDirection,
+                facadeColor,
+                facadeMaterial,
+                roofColor,
+                roofMaterial";
+
+fn attrs_from_row(row: &duckdb::Row, first: usize) -> duckdb::Result<BuildingAttrs> {
+    Ok(BuildingAttrs {
+        min_height: row.get(first)?,
+        min_floor: row.get(first + 1)?,
+        roof_shape: row.get(first + 2)?,
+        roof_height: row.get(first + 3)?,
+        roof_direction: row.get(first + 4)?,
+        facade_color: row.get(first + 5)?,
+        facade_material: row.get(first + 6)?,
+        roof_color: row.get(first + 7)?,
+        roof_material: row.get(first + 8)?,
+        building_id: None,
+    })
+}
+
+pub fn query_buildings(
+    conn: &Connection,
+    params: BuildingsQueryParams,
+) -> Result<(Vec<Building>, LoadReport), MapLoadError> {
+    query_building_rows(conn, params, false)
+}
+
+// https://docs.overturemaps.org/reference/buildings/building_part
+pub fn query_building_parts(
+    conn: &Connection,
+    params: BuildingsQueryParams,
+) -> Result<(Vec<Building>, LoadReport), MapLoadError> {
+    query_building_rows(conn, params, true)
 }
 
-pub fn query_buildings(params: BuildingsQueryParams) -> Vec<Building> {
-    let path = "./data.duckdb";
-    let conn = Connection::open(&path).unwrap();
-    conn.execute_batch("INSTALL httpfs; LOAD httpfs;").unwrap();
-    conn.execute_batch("INSTALL spatial; LOAD spatial;")
-        .unwrap();
-    let from = params.from_string;
+fn query_building_rows(
+    conn: &Connection,
+    params: BuildingsQueryParams,
+    parts: bool,
+) -> Result<(Vec<Building>, LoadReport), MapLoadError> {
+    let from = format!("read_parquet({})", sql_string(&params.path));
+    let where_string: String = match params.bbox {
+        Some(bbox) => format!("WHERE {}", bbox_where(bbox)),
+        None => String::from(""),
+    };
     let limit: String = match params.limit {
         Some(l) => format!("LIMIT {}", l),
         None => String::from(""),
     };
-    let mut stmt = conn
-        .prepare(&format!(
-            "SELECT id,
+    // Parts have no class of their own but point at their building.
+    let (class, building_id) = if parts {
+        ("NULL as class", "buildingId")
+    } else {
+        ("class", "NULL as building_id")
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id,
                 height,
                 JSON(names) as names,
                 geometry,
                 numFloors,
-                class,
-            FROM {from} {limit}"
-        ))
-        .unwrap();
-    #[derive(Debug)]
-    struct DbBuilding {
-        id: String,
-        height: Option<f64>,
-        names: Option<String>,
-        bbox: Vec<u8>,
-        geom: Vec<u8>,
-        num_floors: Option<i32>,
-        class: Option<String>,
-    }
-    let query_iter = stmt
-        .query_map([], |row| {
-            Ok(DbBuilding {
-                id: row.get(0)?,
-                height: row.get(1)?,
-                names: row.get(2)?,
-                bbox: row.get(3)?,
-                geom: row.get(3)?,
-                num_floors: row.get(4)?,
-                class: row.get(5)?,
-            })
+                {class},
+                JSON(sources) as sources,
+                CAST(updateTime AS VARCHAR) as update_time,
+                {ATTRS_COLUMNS},
+                {building_id}
+            FROM {from} {where_string} {limit}"
+    ))?;
+    let query_iter = stmt.query_map([], |row| {
+        Ok(BuildingRow {
+            id: row.get(0)?,
+            height: row.get(1)?,
+            names: row.get(2)?,
+            geom: row.get(3)?,
+            num_floors: row.get(4)?,
+            class: row.get(5)?,
+            sources: row.get(6)?,
+            update_time: row.get(7)?,
+            attrs: BuildingAttrs {
+                building_id: row.get(17)?,
+                ..attrs_from_row(row, 8)?
+            },
         })
-        .unwrap();
+    })?;
 
     let mut buildings: Vec<Building> = vec![];
+    let mut report = LoadReport::default();
     for query_item in query_iter {
-        let query_item = query_item.unwrap();
-        let id = query_item.id;
-        let raw = query_item.geom;
-        // println!("query_item.geom:{:?}", &raw);
-        let mut rdr = std::io::Cursor::new(raw);
-        let g = Geometry::from_wkb(&mut rdr, WkbDialect::Wkb);
-
-        let building_class = query_item.class.map(|c| c.parse().unwrap_or_default());
-        let names = query_item.names.and_then(|n| serde_json::from_str(&n).ok());
-
-        match g {
-            Ok(g) => match g {
-                Geometry::MultiPolygon(multy_polygon) => {
-                    for polygon in multy_polygon {
-                        let exterior = polygon.exterior();
-                        let c1 = exterior
-                            .coords()
-                            .next()
-                            .expect("To take exterior:0 coordinate");
-
-                        for (i, c) in exterior.coords().enumerate() {
-                            if i > 0 {
-                                let dlat = c.x - c1.x;
-                                if dlat > 0.1 {
-                                    println!("{id}:{i}dlat:{dlat}:{:?}", &polygon);
-                                }
-                                let dlon = c.y - c1.y;
-                                if dlon > 0.1 {
-                                    println!("{id}:{i}dlon:{dlon}:{:?}", &polygon);
-                                }
-                            }
-                        }
-
-                        let building = polygon_building(
-                            polygon,
-                            params.k,
-                            params.center,
-                            query_item.height,
-                            query_item.num_floors,
-                        );
-
-                        buildings.push(Building::from_props(
-                            building,
-                            building_class,
-                            names.clone(),
-                        ));
-                    }
-                }
-                Geometry::Polygon(polygon) => {
-                    let exterior = polygon.exterior();
-                    let c1 = exterior
-                        .coords()
-                        .next()
-                        .expect("To take exterior:0 coordinate");
-                    for (i, c) in exterior.coords().enumerate() {
-                        if i > 0 {
-                            let dlat = c.x - c1.x;
-                            if dlat > 0.1 {
-                                println!("{id}:{i}dlat:{dlat}:{:?}", &polygon);
-                            }
-                            let dlon = c.y - c1.y;
-                            if dlon > 0.1 {
-                                println!("{id}:{i}dlon:{dlon}:{:?}", &polygon);
-                            }
-                        }
-                    }
-
-                    let building = polygon_building(
-                        polygon,
-                        params.k,
-                        params.center,
-                        query_item.height,
-                        query_item.num_floors,
-                    );
-
-                    buildings.push(Building::from_props(building, building_class, names));
-                }
-                not_polygon => {
-                    dbg!(&not_polygon);
-                }
-            },
+        let query_item = match query_item {
+            Ok(item) => item,
             Err(e) => {
-                dbg!(e);
+                report.skip(None, e.into());
+                continue;
+            }
+        };
+        let id = query_item.id.clone();
+        match query_item.into_buildings(params.projection, &mut report) {
+            Ok(b) => {
+                buildings.extend(b);
+                report.loaded += 1;
             }
+            Err(e) => report.skip(Some(&id), e),
         }
     }
-    buildings
+    Ok((buildings, report))
 }
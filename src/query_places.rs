@@ -0,0 +1,112 @@
+use duckdb::Connection;
+use geo_types::Geometry;
+use geozero::wkb::FromWkb;
+use geozero::wkb::WkbDialect;
+
+use crate::data_source::bbox_where;
+use crate::error::{LoadReport, MapLoadError};
+use crate::geo_util::geometry_type;
+use crate::place::{Categories, Place};
+use crate::projection::Projection;
+use crate::sql::sql_string;
+use crate::Names;
+
+// https://docs.overturemaps.org/reference/places/place
+
+#[derive(Clone)]
+pub struct PlacesQueryParams {
+    /// Parquet file, or anything `read_parquet` accepts for the DuckDB source.
+    pub path: String,
+    /// `[lon_min, lat_min, lon_max, lat_max]`, see `data_source::bbox_where`.
+    pub bbox: Option<[f64; 4]>,
+    pub limit: Option<u32>,
+    pub projection: Projection,
+}
+
+/// Place columns as read by any `MapDataSource`.
+#[derive(Debug)]
+pub struct PlaceRow {
+    pub id: String,
+    /// `names` as JSON.
+    pub names: Option<String>,
+    /// `categories` as JSON.
+    pub categories: Option<String>,
+    pub confidence: Option<f64>,
+    /// WKB.
+    pub geom: Vec<u8>,
+}
+
+impl PlaceRow {
+    pub fn into_place(self, projection: Projection) -> Result<Place, MapLoadError> {
+        let mut rdr = std::io::Cursor::new(self.geom);
+        let point = match Geometry::from_wkb(&mut rdr, WkbDialect::Wkb)? {
+            Geometry::Point(point) => point,
+            not_point => return Err(MapLoadError::UnsupportedGeometry(geometry_type(&not_point))),
+        };
+        let names: Option<Names> = self.names.map(|n| serde_json::from_str(&n)).transpose()?;
+        let categories: Option<Categories> = self
+            .categories
+            .map(|c| serde_json::from_str(&c))
+            .transpose()?;
+
+        Ok(Place {
+            names,
+            categories,
+            confidence: self.confidence,
+            translate: projection.forward(point.x(), point.y()),
+        })
+    }
+}
+
+pub fn query_places(
+    conn: &Connection,
+    params: PlacesQueryParams,
+) -> Result<(Vec<Place>, LoadReport), MapLoadError> {
+    let from = format!("read_parquet({})", sql_string(&params.path));
+    let where_string: String = match params.bbox {
+        Some(bbox) => format!("WHERE {}", bbox_where(bbox)),
+        None => String::from(""),
+    };
+    let limit: String = match params.limit {
+        Some(l) => format!("LIMIT {}", l),
+        None => String::from(""),
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id,
+                JSON(names) as names,
+                JSON(categories) as categories,
+                confidence,
+                geometry,
+            FROM {from} {where_string} {limit}"
+    ))?;
+    let query_iter = stmt.query_map([], |row| {
+        Ok(PlaceRow {
+            id: row.get(0)?,
+            names: row.get(1)?,
+            categories: row.get(2)?,
+            confidence: row.get(3)?,
+            geom: row.get(4)?,
+        })
+    })?;
+
+    let mut places: Vec<Place> = vec![];
+    let mut report = LoadReport::default();
+    for query_item in query_iter {
+        let query_item = match query_item {
+            Ok(item) => item,
+            Err(e) => {
+                report.skip(None, e.into());
+                continue;
+            }
+        };
+        let id = query_item.id.clone();
+        match query_item.into_place(params.projection) {
+            Ok(place) => {
+                places.push(place);
+                report.loaded += 1;
+            }
+            Err(e) => report.skip(Some(&id), e),
+        }
+    }
+    Ok((places, report))
+}
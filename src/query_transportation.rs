@@ -3,101 +3,148 @@ use geo_types::Geometry;
 use geozero::wkb::FromWkb;
 use geozero::wkb::WkbDialect;
 
+use crate::data_source::bbox_where;
+use crate::error::{LoadReport, MapLoadError};
+use crate::geo_util::geometry_type;
+use crate::overture::FeatureMeta;
+use crate::projection::Projection;
+use crate::road_properties::RoadProperties;
+use crate::sql::sql_string;
+use crate::transportation::line_string_road;
 use crate::transportation::RoadClass;
-use crate::transportation::Segment;
-use crate::transportation::{line_string_road, Road};
-use crate::KxyGeodesic;
+use crate::transportation::{ConnectorRef, Segment, SegmentConnector};
 
+#[derive(Clone)]
 pub struct TransportationQueryParams {
-    pub from_string: String,
-    pub k: KxyGeodesic,
+    /// Parquet file, or anything `read_parquet` accepts for the DuckDB source.
+    pub path: String,
+    /// `[lon_min, lat_min, lon_max, lat_max]`, see `data_source::bbox_where`.
+    pub bbox: Option<[f64; 4]>,
     pub limit: Option<u32>,
-    pub center: [f64; 2],
+    pub projection: Projection,
+}
+
+/// Segment columns as read by any `MapDataSource`.
+#[derive(Debug)]
+pub struct SegmentRow {
+    pub id: String,
+    /// WKB.
+    pub geom: Vec<u8>,
+    /// `road` JSON string.
+    pub road: Option<String>,
+    pub level: Option<i32>,
+    /// `connectors` as JSON.
+    pub connectors: Option<String>,
+    /// Metres.
+    pub width: Option<f64>,
+    /// `sources` as JSON.
+    pub sources: Option<String>,
+    pub update_time: Option<String>,
+}
+
+impl SegmentRow {
+    /// `Ok(None)` for segments without road properties.
+    pub fn into_segment(self, projection: Projection) -> Result<Option<Segment>, MapLoadError> {
+        let mut rdr = std::io::Cursor::new(self.geom);
+        let line_string = match Geometry::from_wkb(&mut rdr, WkbDialect::Wkb)? {
+            Geometry::LineString(line_string) => line_string,
+            not_line_string => {
+                return Err(MapLoadError::UnsupportedGeometry(geometry_type(
+                    &not_line_string,
+                )))
+            }
+        };
+        if line_string.0.len() < 2 {
+            return Err(MapLoadError::UnsupportedGeometry(
+                "LineString with less than 2 points",
+            ));
+        }
+        let Some(road) = &self.road else {
+            return Ok(None);
+        };
+        let road: RoadProperties = serde_json::from_str(road)?;
+        let connectors: Vec<ConnectorRef> = self
+            .connectors
+            .map(|c| serde_json::from_str(&c))
+            .transpose()?
+            .unwrap_or_default();
+        let (translate, line) = line_string_road(line_string, projection);
+        let road_class: RoadClass = RoadClass::from_string(&road.class);
+        Ok(Some(Segment {
+            meta: FeatureMeta::new(self.id, self.sources, self.update_time)?,
+            translate,
+            line,
+            road_class,
+            road,
+            width: self.width.map(|w| w as f32),
+            level: self.level,
+            connectors: connectors.into_iter().map(SegmentConnector::from).collect(),
+            ramp_levels: [None, None],
+        }))
+    }
 }
 
 // https://docs.overturemaps.org/reference/transportation/segment
 // https://github.com/alexichepura/overture_maps_rs/issues/1
 
-pub fn query_transportation(params: TransportationQueryParams) -> Vec<Segment> {
-    let path = "./data.duckdb";
-    let conn = Connection::open(&path).unwrap();
-    conn.execute_batch("INSTALL httpfs; LOAD httpfs;").unwrap();
-    conn.execute_batch("INSTALL spatial; LOAD spatial;")
-        .unwrap();
-    let from = params.from_string;
+pub fn query_transportation(
+    conn: &Connection,
+    params: TransportationQueryParams,
+) -> Result<(Vec<Segment>, LoadReport), MapLoadError> {
+    let from = format!("read_parquet({})", sql_string(&params.path));
+    let where_string: String = match params.bbox {
+        Some(bbox) => format!("WHERE {}", bbox_where(bbox)),
+        None => String::from(""),
+    };
     let limit: String = match params.limit {
         Some(l) => format!("LIMIT {}", l),
         None => String::from(""),
     };
-    let mut stmt = conn
-        .prepare(&format!(
-            "SELECT
+    let mut stmt = conn.prepare(&format!(
+        "SELECT
                 id,
                 geometry,
                 road,
-                level
-                -- width
-                FROM {from} {limit}"
-        ))
-        .unwrap();
-    #[derive(Debug)]
-    struct DbSegment {
-        // id: String,
-        geom: Vec<u8>,
-        road: Option<String>,
-        // level: Option<u32>,
-        // connectors: Option<String>,
-        // width: Option<f32>,
-    }
+                level,
+                JSON(connectors) as connectors,
+                width,
+                JSON(sources) as sources,
+                CAST(updateTime AS VARCHAR) as update_time
+                FROM {from} {where_string} {limit}"
+    ))?;
 
-    let now = std::time::Instant::now();
-    let query_iter = stmt
-        .query_map([], |row| {
-            Ok(DbSegment {
-                // id: row.get(0)?,
-                geom: row.get(1)?,
-                road: row.get(2)?,
-                // level: row.get(3)?,
-                // width: row.get(4)?,
-            })
+    let query_iter = stmt.query_map([], |row| {
+        Ok(SegmentRow {
+            id: row.get(0)?,
+            geom: row.get(1)?,
+            road: row.get(2)?,
+            level: row.get(3)?,
+            connectors: row.get(4)?,
+            width: row.get(5)?,
+            sources: row.get(6)?,
+            update_time: row.get(7)?,
         })
-        .unwrap();
-    println!("{:?}", now.elapsed());
+    })?;
     let mut segments: Vec<Segment> = vec![];
+    let mut report = LoadReport::default();
     for item in query_iter {
-        let item = item.unwrap();
-        let raw = item.geom;
-        let mut rdr = std::io::Cursor::new(raw);
-        let g = Geometry::from_wkb(&mut rdr, WkbDialect::Wkb);
-        match g {
-            Ok(g) => match g {
-                Geometry::LineString(line_string) => {
-                    if let Some(road) = &item.road {
-                        // dbg!(&road);
-                        // dbg!(&item.level);
-                        let (translate, line) =
-                            line_string_road(line_string, params.k, params.center);
-                        let road_parsed: Road = serde_json::from_str(road).expect("road");
-                        let road_class: RoadClass = RoadClass::from_string(&road_parsed.class);
-                        let segment = Segment {
-                            translate,
-                            line,
-                            k: params.k,
-                            road_class,
-                            width: None, //item.width,
-                        };
-                        segments.push(segment);
-                    }
-                }
-                not_line_string => {
-                    dbg!(&not_line_string);
-                }
-            },
+        let item = match item {
+            Ok(item) => item,
             Err(e) => {
-                dbg!(e);
+                report.skip(None, e.into());
+                continue;
+            }
+        };
+        let id = item.id.clone();
+        match item.into_segment(params.projection) {
+            Ok(Some(segment)) => {
+                segments.push(segment);
+                report.loaded += 1;
             }
+            Ok(None) => {}
+            Err(e) => report.skip(Some(&id), e),
         }
     }
 
-    segments
+    Ok((segments, report))
 }
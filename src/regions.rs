@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::map_config::{ConfigError, Layer};
+
+// See `regions.example.yaml`.
+
+pub const REGIONS_PATH: &str = "regions.yaml";
+
+/// A named area of the manifest. Its extent is `bbox`, else a square
+/// `radius` metres out from lon/lat, else the bbox of `clip`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Region {
+    pub name: String,
+    /// Centre, the middle of `bbox` when not given.
+    pub lon: Option<f64>,
+    pub lat: Option<f64>,
+    pub radius: Option<f64>,
+    /// `[lon_min, lat_min, lon_max, lat_max]`
+    pub bbox: Option<[f64; 4]>,
+    /// GeoJSON polygon, relative to the manifest.
+    pub clip: Option<PathBuf>,
+    pub layers: Option<Vec<Layer>>,
+}
+
+impl Region {
+    /// `[lon, lat]` the area's files are named after and the map is centred on.
+    pub fn centre(&self) -> Option<[f64; 2]> {
+        match (self.lon, self.lat, self.bbox) {
+            (Some(lon), Some(lat), _) => Some([lon, lat]),
+            (None, None, Some([lon_min, lat_min, lon_max, lat_max])) => {
+                Some([(lon_min + lon_max) / 2., (lat_min + lat_max) / 2.])
+            }
+            _ => None,
+        }
+    }
+}
+
+/// `regions.yaml`, the named areas `cli cache-all` extracts and `--region` picks from.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RegionManifest {
+    pub parquet_dir: Option<PathBuf>,
+    pub overture_root: Option<String>,
+    pub release: Option<String>,
+    pub regions: Vec<Region>,
+}
+
+impl RegionManifest {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let f = std::fs::File::open(path).map_err(|e| ConfigError::Io(path.into(), e))?;
+        let mut manifest: RegionManifest =
+            serde_yaml::from_reader(f).map_err(|e| ConfigError::Yaml(path.into(), e))?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut names = HashSet::new();
+        for region in manifest.regions.iter_mut() {
+            if !names.insert(region.name.clone()) {
+                return Err(ConfigError::Invalid(
+                    "regions",
+                    format!("{:?} is listed twice", region.name),
+                ));
+            }
+            if region.centre().is_none() {
+                return Err(ConfigError::Invalid(
+                    "regions",
+                    format!("{:?} needs lon and lat, or a bbox", region.name),
+                ));
+            }
+            if let Some(clip) = &mut region.clip {
+                *clip = dir.join(&*clip);
+            }
+        }
+        Ok(manifest)
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Region, ConfigError> {
+        self.regions.iter().find(|r| r.name == name).ok_or_else(|| {
+            let names: Vec<&str> = self.regions.iter().map(|r| r.name.as_str()).collect();
+            ConfigError::Invalid(
+                "region",
+                format!("{name:?} is not one of {}", names.join(", ")),
+            )
+        })
+    }
+}
+
+/// One layer file of a `CacheRecord`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LayerRecord {
+    pub layer: Layer,
+    pub file: PathBuf,
+    pub rows: u64,
+    pub bytes: u64,
+    pub blake3: String,
+}
+
+/// What `cli cache-all` extracted for a region, `{area}_cache.yaml` next
+/// to its parquet files.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheRecord {
+    pub region: String,
+    pub release: Option<String>,
+    pub bbox: [f64; 4],
+    pub layers: Vec<LayerRecord>,
+}
+
+impl CacheRecord {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let f = std::fs::File::open(path).map_err(|e| ConfigError::Io(path.into(), e))?;
+        serde_yaml::from_reader(f).map_err(|e| ConfigError::Yaml(path.into(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        let f = std::fs::File::create(path).map_err(|e| ConfigError::Io(path.into(), e))?;
+        serde_yaml::to_writer(f, self).map_err(|e| ConfigError::Yaml(path.into(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_config::tests::{lock_env, temp_file};
+    use crate::map_config::{MapArgs, MapConfig};
+
+    const BERLIN: &str = "  - name: berlin\n    lon: 13.4\n    lat: 52.52\n    radius: 2000\n";
+
+    fn invalid_field(result: Result<RegionManifest, ConfigError>) -> &'static str {
+        match result {
+            Err(ConfigError::Invalid(field, _)) => field,
+            other => panic!("expected Invalid, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        let path = temp_file("duplicate.yaml", &format!("regions:\n{BERLIN}{BERLIN}"));
+        assert_eq!(invalid_field(RegionManifest::load(&path)), "regions");
+    }
+
+    #[test]
+    fn rejects_regions_without_centre() {
+        let path = temp_file(
+            "no-centre.yaml",
+            "regions:\n  - name: nowhere\n    radius: 10\n",
+        );
+        assert_eq!(invalid_field(RegionManifest::load(&path)), "regions");
+    }
+
+    #[test]
+    fn bbox_centre() {
+        let path = temp_file(
+            "bbox.yaml",
+            "regions:\n  - name: box\n    bbox: [10.0, 50.0, 12.0, 54.0]\n",
+        );
+        let manifest = RegionManifest::load(&path).unwrap();
+        assert_eq!(manifest.get("box").unwrap().centre(), Some([11., 52.]));
+        assert!(matches!(
+            manifest.get("berlin"),
+            Err(ConfigError::Invalid("region", _))
+        ));
+    }
+
+    #[test]
+    fn clip_relative_to_the_manifest() {
+        let path = temp_file(
+            "clip.yaml",
+            &format!("regions:\n{BERLIN}    clip: shapes/berlin.geojson\n"),
+        );
+        let manifest = RegionManifest::load(&path).unwrap();
+        assert_eq!(
+            manifest.regions[0].clip,
+            Some(path.parent().unwrap().join("shapes/berlin.geojson"))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        for (name, yaml) in [
+            (
+                "unknown-region.yaml",
+                format!("regions:\n{BERLIN}    zoom: 3\n"),
+            ),
+            ("unknown-top.yaml", format!("zoom: 3\nregions:\n{BERLIN}")),
+        ] {
+            let result = RegionManifest::load(&temp_file(name, &yaml));
+            assert!(
+                matches!(&result, Err(ConfigError::Yaml(_, e)) if e.to_string().contains("zoom")),
+                "{result:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn cache_record_round_trip() {
+        let record = CacheRecord {
+            region: "berlin".to_string(),
+            release: Some("2023-11-14-alpha.0".to_string()),
+            bbox: [13.37, 52.5, 13.43, 52.54],
+            layers: vec![LayerRecord {
+                layer: Layer::BuildingParts,
+                file: PathBuf::from("parquet/13.4_52.52_berlin_building_part.parquet"),
+                rows: 1234,
+                bytes: 56789,
+                blake3: "af13".to_string(),
+            }],
+        };
+        let path = temp_file("cache.yaml", "");
+        record.save(&path).unwrap();
+        let loaded = CacheRecord::load(&path).unwrap();
+        assert_eq!(loaded.region, record.region);
+        assert_eq!(loaded.release, record.release);
+        assert_eq!(loaded.bbox, record.bbox);
+        assert_eq!(loaded.layers.len(), 1);
+        let (a, b) = (&loaded.layers[0], &record.layers[0]);
+        assert_eq!(
+            (a.layer, &a.file, a.rows, a.bytes, &a.blake3),
+            (b.layer, &b.file, b.rows, b.bytes, &b.blake3)
+        );
+    }
+
+    #[test]
+    fn region_over_env() {
+        let _env = lock_env();
+        let path = temp_file(
+            "env.yaml",
+            &format!("parquet_dir: cache\nregions:\n{BERLIN}"),
+        );
+        std::env::set_var("MAP_LON", "2.35");
+        std::env::set_var("MAP_LAT", "48.85");
+        std::env::set_var("MAP_NAME", "paris");
+        let args = MapArgs {
+            region: Some("berlin".to_string()),
+            regions: Some(path),
+            ..Default::default()
+        };
+        let config = MapConfig::load(&args).unwrap();
+        assert_eq!((config.lon, config.lat), (13.4, 52.52));
+        assert_eq!(config.name, "berlin");
+        assert_eq!(config.parquet_dir, PathBuf::from("cache"));
+        assert_eq!(config.region.map(|r| r.name).as_deref(), Some("berlin"));
+    }
+}
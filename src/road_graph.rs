@@ -0,0 +1,448 @@
+use bevy::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+use crate::tiles::{poll_tiles, MapTiles, TileCoord};
+use crate::transportation::{RoadClass, Segment};
+
+// https://docs.overturemaps.org/reference/transportation/connector
+
+pub type NodeId = usize;
+pub type EdgeId = usize;
+
+/// A transportation connector.
+#[derive(Debug, Clone)]
+pub struct RoadNode {
+    pub id: String,
+    /// World x/z in metres.
+    pub position: [f64; 2],
+}
+
+/// The part of a `Segment` between two consecutive connectors.
+#[derive(Debug, Clone)]
+pub struct RoadEdge {
+    pub segment_id: String,
+    pub from: NodeId,
+    pub to: NodeId,
+    /// Metres.
+    pub length: f64,
+    pub road_class: RoadClass,
+    /// World x/z in metres, `from` to `to`.
+    pub points: Vec<[f64; 2]>,
+}
+
+/// Road network of the loaded tiles: nodes are connectors, edges are
+/// segments split at the connectors along them. Undirected.
+#[derive(Resource, Default)]
+pub struct RoadGraph {
+    pub nodes: Vec<RoadNode>,
+    pub edges: Vec<RoadEdge>,
+    node_index: HashMap<String, NodeId>,
+    adjacency: Vec<Vec<EdgeId>>,
+    /// Tiles the graph was built from.
+    tiles: HashSet<TileCoord>,
+}
+
+impl RoadGraph {
+    pub fn build<'a>(segments: impl Iterator<Item = &'a Segment>) -> Self {
+        let segments: Vec<&Segment> = segments.collect();
+        let mut graph = RoadGraph::default();
+
+        // Connector positions: first from `at` and segment ends, then the
+        // remaining bare ids from wherever another segment placed them.
+        let mut placed: HashMap<&str, [f64; 2]> = HashMap::new();
+        for segment in segments.iter() {
+            let last = segment.connectors.len().saturating_sub(1);
+            for (i, connector) in segment.connectors.iter().enumerate() {
+                let at = connector.at.or(match i {
+                    0 => Some(0.),
+                    i if i == last => Some(1.),
+                    _ => None,
+                });
+                if let Some(at) = at {
+                    let point = point_at(segment, at * segment.length());
+                    placed.entry(connector.id.as_str()).or_insert(point);
+                }
+            }
+        }
+
+        for segment in segments {
+            let length = segment.length();
+            let mut stops: Vec<(f64, NodeId)> = vec![];
+            for connector in segment.connectors.iter() {
+                let distance = match connector.at {
+                    Some(at) => at * length,
+                    None => match placed.get(connector.id.as_str()) {
+                        Some(position) => locate(segment, *position),
+                        None => continue,
+                    },
+                };
+                let position = placed
+                    .get(connector.id.as_str())
+                    .copied()
+                    .unwrap_or_else(|| point_at(segment, distance));
+                let node = graph.node(&connector.id, position);
+                stops.push((distance, node));
+            }
+            stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            for [(from_distance, from), (to_distance, to)] in stops.array_windows().copied() {
+                if from == to {
+                    continue;
+                }
+                graph.add_edge(RoadEdge {
+                    segment_id: segment.meta.id.clone(),
+                    from,
+                    to,
+                    length: to_distance - from_distance,
+                    road_class: segment.road_class,
+                    points: substring(segment, from_distance, to_distance),
+                });
+            }
+        }
+        graph
+    }
+
+    fn node(&mut self, id: &str, position: [f64; 2]) -> NodeId {
+        if let Some(node) = self.node_index.get(id) {
+            return *node;
+        }
+        let node = self.nodes.len();
+        self.nodes.push(RoadNode {
+            id: id.to_string(),
+            position,
+        });
+        self.adjacency.push(vec![]);
+        self.node_index.insert(id.to_string(), node);
+        node
+    }
+
+    fn add_edge(&mut self, edge: RoadEdge) {
+        let id = self.edges.len();
+        self.adjacency[edge.from].push(id);
+        self.adjacency[edge.to].push(id);
+        self.edges.push(edge);
+    }
+
+    pub fn node_by_id(&self, id: &str) -> Option<NodeId> {
+        self.node_index.get(id).copied()
+    }
+
+    /// Adjacent nodes with the edge leading to each.
+    pub fn neighbours(&self, node: NodeId) -> impl Iterator<Item = (NodeId, &RoadEdge)> {
+        self.adjacency[node].iter().map(move |e| {
+            let edge = &self.edges[*e];
+            let other = if edge.from == node {
+                edge.to
+            } else {
+                edge.from
+            };
+            (other, edge)
+        })
+    }
+
+    /// Connected components, largest first.
+    pub fn components(&self) -> Vec<Vec<NodeId>> {
+        let mut seen = vec![false; self.nodes.len()];
+        let mut components: Vec<Vec<NodeId>> = vec![];
+        for start in 0..self.nodes.len() {
+            if seen[start] {
+                continue;
+            }
+            seen[start] = true;
+            let mut component = vec![start];
+            let mut i = 0;
+            while i < component.len() {
+                for (next, _) in self.neighbours(component[i]) {
+                    if !seen[next] {
+                        seen[next] = true;
+                        component.push(next);
+                    }
+                }
+                i += 1;
+            }
+            components.push(component);
+        }
+        components.sort_by_key(|c| std::cmp::Reverse(c.len()));
+        components
+    }
+
+    pub fn edges_of(&self, node: NodeId) -> &[EdgeId] {
+        &self.adjacency[node]
+    }
+
+    /// Closest node to a world x/z position.
+    pub fn nearest_node(&self, position: [f64; 2]) -> Option<NodeId> {
+        self.nearest_node_by(position, |_| true)
+    }
+
+    /// Closest node to a world x/z position among those passing `filter`.
+    pub fn nearest_node_by(
+        &self,
+        position: [f64; 2],
+        filter: impl Fn(NodeId) -> bool,
+    ) -> Option<NodeId> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| filter(*i))
+            .min_by(|(_, a), (_, b)| {
+                distance(a.position, position).total_cmp(&distance(b.position, position))
+            })
+            .map(|(i, _)| i)
+    }
+}
+
+fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    (b[0] - a[0]).hypot(b[1] - a[1])
+}
+
+/// World position `along` metres from the segment start.
+fn point_at(segment: &Segment, along: f64) -> [f64; 2] {
+    let t = segment.translate;
+    let mut walked = 0.;
+    for [a, b] in segment.line.array_windows() {
+        let d = distance(*a, *b);
+        if walked + d >= along && d > 0. {
+            let f = (along - walked) / d;
+            return [
+                t[0] + a[0] + (b[0] - a[0]) * f,
+                t[1] + a[1] + (b[1] - a[1]) * f,
+            ];
+        }
+        walked += d;
+    }
+    let last = segment.line[segment.line.len() - 1];
+    [t[0] + last[0], t[1] + last[1]]
+}
+
+/// Distance along the segment of the closest point to a world position.
+fn locate(segment: &Segment, position: [f64; 2]) -> f64 {
+    let p = [
+        position[0] - segment.translate[0],
+        position[1] - segment.translate[1],
+    ];
+    let mut walked = 0.;
+    let mut best = (f64::MAX, 0.);
+    for [a, b] in segment.line.array_windows() {
+        let d = distance(*a, *b);
+        let f = if d > 0. {
+            (((p[0] - a[0]) * (b[0] - a[0]) + (p[1] - a[1]) * (b[1] - a[1])) / (d * d))
+                .clamp(0., 1.)
+        } else {
+            0.
+        };
+        let closest = [a[0] + (b[0] - a[0]) * f, a[1] + (b[1] - a[1]) * f];
+        let off = distance(closest, p);
+        if off < best.0 {
+            best = (off, walked + d * f);
+        }
+        walked += d;
+    }
+    best.1
+}
+
+/// World positions of the segment between two distances along it.
+fn substring(segment: &Segment, from: f64, to: f64) -> Vec<[f64; 2]> {
+    let t = segment.translate;
+    let mut points = vec![point_at(segment, from)];
+    let mut walked = 0.;
+    for [a, b] in segment.line.array_windows() {
+        walked += distance(*a, *b);
+        if walked > from && walked < to {
+            points.push([t[0] + b[0], t[1] + b[1]]);
+        }
+    }
+    points.push(point_at(segment, to));
+    points
+}
+
+/// Rebuilds the graph from scratch whenever the set of loaded tiles changes.
+/// Every segment of every loaded tile is walked again, so each tile loaded or
+/// dropped costs time linear in all loaded segments, spent on the main thread
+/// in that frame; the log line below says how long it took.
+pub fn update_road_graph(map_tiles: Res<MapTiles>, mut graph: ResMut<RoadGraph>) {
+    let loaded: HashSet<TileCoord> = map_tiles
+        .tiles
+        .iter()
+        .filter(|(_, tile)| tile.task.is_none())
+        .map(|(coord, _)| *coord)
+        .collect();
+    if loaded == graph.tiles {
+        return;
+    }
+    let now = std::time::Instant::now();
+    let mut rebuilt = RoadGraph::build(
+        loaded
+            .iter()
+            .flat_map(|coord| map_tiles.tiles[coord].segments.iter()),
+    );
+    rebuilt.tiles = loaded;
+    info!(
+        "road graph: {} nodes, {} edges, {} components in {:?}",
+        rebuilt.nodes.len(),
+        rebuilt.edges.len(),
+        rebuilt.components().len(),
+        now.elapsed()
+    );
+    *graph = rebuilt;
+}
+
+pub struct RoadGraphPlugin;
+
+impl Plugin for RoadGraphPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RoadGraph>()
+            .add_systems(Update, update_road_graph.after(poll_tiles));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overture::FeatureMeta;
+    use crate::road_properties::RoadProperties;
+    use crate::transportation::SegmentConnector;
+
+    fn segment(id: &str, line: &[[f64; 2]], connectors: &[(&str, Option<f64>)]) -> Segment {
+        Segment {
+            meta: FeatureMeta {
+                id: id.to_string(),
+                sources: vec![],
+                update_time: None,
+            },
+            translate: [0., 0.],
+            line: line.to_vec(),
+            road_class: RoadClass::Residential,
+            road: RoadProperties {
+                class: "residential".to_string(),
+                names: None,
+                surface: None,
+                flags: None,
+                lanes: None,
+                restrictions: None,
+            },
+            width: None,
+            level: None,
+            connectors: connectors
+                .iter()
+                .map(|(id, at)| SegmentConnector {
+                    id: id.to_string(),
+                    at: *at,
+                })
+                .collect(),
+            ramp_levels: [None, None],
+        }
+    }
+
+    fn edge_between<'a>(graph: &'a RoadGraph, from: &str, to: &str) -> &'a RoadEdge {
+        let (from, to) = (
+            graph.node_by_id(from).unwrap(),
+            graph.node_by_id(to).unwrap(),
+        );
+        graph
+            .neighbours(from)
+            .find(|(node, _)| *node == to)
+            .map(|(_, edge)| edge)
+            .unwrap()
+    }
+
+    #[test]
+    fn splits_at_interior_connectors() {
+        let road = segment(
+            "road",
+            &[[0., 0.], [100., 0.], [100., 200.]],
+            &[("a", Some(0.)), ("m", Some(0.25)), ("b", Some(1.))],
+        );
+        let graph = RoadGraph::build([road].iter());
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.edges.len(), 2);
+        assert_eq!(
+            graph.nodes[graph.node_by_id("m").unwrap()].position,
+            [75., 0.]
+        );
+
+        let first = edge_between(&graph, "a", "m");
+        assert_eq!(first.length, 75.);
+        assert_eq!(first.points, [[0., 0.], [75., 0.]]);
+        let second = edge_between(&graph, "m", "b");
+        assert_eq!(second.length, 225.);
+        assert_eq!(second.points, [[75., 0.], [100., 0.], [100., 200.]]);
+        assert_eq!(second.segment_id, "road");
+    }
+
+    #[test]
+    fn places_connectors_without_at() {
+        // `x` has no `at` on the main road but starts the side road.
+        let main = segment(
+            "main",
+            &[[0., 0.], [200., 0.]],
+            &[("a", None), ("x", None), ("lost", None), ("b", None)],
+        );
+        let side = segment(
+            "side",
+            &[[50., 0.], [50., 100.]],
+            &[("x", None), ("y", None)],
+        );
+        let graph = RoadGraph::build([main, side].iter());
+
+        // Ends default to 0 and 1, `lost` is nowhere else and is dropped.
+        assert_eq!(graph.nodes.len(), 4);
+        assert_eq!(graph.node_by_id("lost"), None);
+        assert_eq!(
+            graph.nodes[graph.node_by_id("b").unwrap()].position,
+            [200., 0.]
+        );
+        assert_eq!(
+            graph.nodes[graph.node_by_id("x").unwrap()].position,
+            [50., 0.]
+        );
+        assert_eq!(edge_between(&graph, "a", "x").length, 50.);
+        assert_eq!(edge_between(&graph, "x", "b").length, 150.);
+        assert_eq!(edge_between(&graph, "x", "y").length, 100.);
+        assert_eq!(graph.edges_of(graph.node_by_id("x").unwrap()).len(), 3);
+    }
+
+    #[test]
+    fn components_largest_first() {
+        let segments = [
+            segment(
+                "island",
+                &[[500., 0.], [600., 0.]],
+                &[("p", None), ("q", None)],
+            ),
+            segment("ab", &[[0., 0.], [100., 0.]], &[("a", None), ("b", None)]),
+            segment(
+                "bc",
+                &[[100., 0.], [100., 100.]],
+                &[("b", None), ("c", None)],
+            ),
+        ];
+        let graph = RoadGraph::build(segments.iter());
+        let components: Vec<Vec<&str>> = graph
+            .components()
+            .into_iter()
+            .map(|c| {
+                let mut ids: Vec<&str> = c.iter().map(|n| graph.nodes[*n].id.as_str()).collect();
+                ids.sort();
+                ids
+            })
+            .collect();
+        assert_eq!(components, [vec!["a", "b", "c"], vec!["p", "q"]]);
+    }
+
+    #[test]
+    fn nearest_node() {
+        let road = segment(
+            "road",
+            &[[0., 0.], [100., 0.]],
+            &[("a", None), ("m", Some(0.5)), ("b", None)],
+        );
+        let graph = RoadGraph::build([road].iter());
+        let id = |node: Option<NodeId>| node.map(|n| graph.nodes[n].id.as_str());
+        assert_eq!(id(graph.nearest_node([40., 30.])), Some("m"));
+        assert_eq!(id(graph.nearest_node([-50., 0.])), Some("a"));
+        let m = graph.node_by_id("m").unwrap();
+        assert_eq!(id(graph.nearest_node_by([40., 30.], |n| n != m)), Some("a"));
+        assert_eq!(RoadGraph::default().nearest_node([0., 0.]), None);
+    }
+}
@@ -0,0 +1,285 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Names;
+
+// https://docs.overturemaps.org/reference/transportation/segment
+// The `road` column. Most properties are either a plain value for the whole
+// segment or a list of rules, each optionally limited to an `at` range.
+
+/// `[from, to]`, fractions 0..=1 along the segment.
+pub type LinearRange = [f64; 2];
+
+/// `Rules` is tried first: a list value such as `lanes` would otherwise
+/// swallow the rules form.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Ranged<T> {
+    Rules(Vec<AtRule<T>>),
+    Value(T),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AtRule<T> {
+    pub at: Option<LinearRange>,
+    #[serde(alias = "values")]
+    pub value: T,
+}
+
+impl<T> Ranged<T> {
+    /// The value covering the whole segment, or the first rule's.
+    pub fn main(&self) -> Option<&T> {
+        match self {
+            Ranged::Value(v) => Some(v),
+            Ranged::Rules(rules) => rules
+                .iter()
+                .find(|r| r.at.is_none())
+                .or(rules.first())
+                .map(|r| &r.value),
+        }
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = (Option<LinearRange>, &T)> {
+        let (value, rules) = match self {
+            Ranged::Value(v) => (Some(v), &[][..]),
+            Ranged::Rules(rules) => (None, rules.as_slice()),
+        };
+        value
+            .map(|v| (None, v))
+            .into_iter()
+            .chain(rules.iter().map(|r| (r.at, &r.value)))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RoadProperties {
+    pub class: String,
+    #[serde(alias = "roadNames")]
+    pub names: Option<Names>,
+    pub surface: Option<Ranged<String>>,
+    pub flags: Option<Ranged<Vec<String>>>,
+    pub lanes: Option<Ranged<Vec<Lane>>>,
+    pub restrictions: Option<Restrictions>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Lane {
+    /// `forward`, `backward`, `bothWays`, ...
+    pub direction: Option<String>,
+    /// Per-lane access and speed rules, kept as they come.
+    pub restrictions: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Restrictions {
+    #[serde(default)]
+    pub speed_limits: Vec<SpeedLimit>,
+    #[serde(default)]
+    pub access: Vec<AccessRule>,
+}
+
+/// `[value, unit]`, e.g. `[50, "km/h"]`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Speed(pub f64, pub SpeedUnit);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SpeedUnit {
+    #[serde(rename = "km/h")]
+    Kmh,
+    #[serde(rename = "mph")]
+    Mph,
+}
+
+impl Speed {
+    pub fn kmh(&self) -> f64 {
+        match self.1 {
+            SpeedUnit::Kmh => self.0,
+            SpeedUnit::Mph => self.0 * 1.609344,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeedLimit {
+    pub max_speed: Option<Speed>,
+    pub min_speed: Option<Speed>,
+    pub is_max_speed_variable: Option<bool>,
+    pub at: Option<LinearRange>,
+    /// Conditions (vehicle, heading, time) kept as they come.
+    pub when: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum AccessKind {
+    Allowed,
+    Denied,
+    Designated,
+}
+
+/// One of `{"allowed": {...}}`, `{"denied": {...}}`, `{"designated": {...}}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessRule {
+    pub allowed: Option<AccessCondition>,
+    pub denied: Option<AccessCondition>,
+    pub designated: Option<AccessCondition>,
+    pub at: Option<LinearRange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AccessCondition {
+    pub when: Option<serde_json::Value>,
+}
+
+impl AccessRule {
+    pub fn kind(&self) -> Option<(AccessKind, &AccessCondition)> {
+        if let Some(c) = &self.allowed {
+            Some((AccessKind::Allowed, c))
+        } else if let Some(c) = &self.denied {
+            Some((AccessKind::Denied, c))
+        } else {
+            self.designated
+                .as_ref()
+                .map(|c| (AccessKind::Designated, c))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OneWay {
+    /// Only along the segment geometry.
+    Forward,
+    Backward,
+}
+
+impl RoadProperties {
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags
+            .as_ref()
+            .is_some_and(|f| f.values().any(|(_, flags)| flags.iter().any(|f| f == flag)))
+    }
+
+    pub fn is_bridge(&self) -> bool {
+        self.has_flag("isBridge")
+    }
+
+    pub fn is_tunnel(&self) -> bool {
+        self.has_flag("isTunnel")
+    }
+
+    pub fn surface(&self) -> Option<&str> {
+        self.surface.as_ref()?.main().map(String::as_str)
+    }
+
+    pub fn lane_count(&self) -> Option<usize> {
+        self.lanes.as_ref()?.main().map(Vec::len)
+    }
+
+    /// Unconditional maximum speed for the whole segment, in km/h.
+    pub fn max_speed_kmh(&self) -> Option<f64> {
+        let restrictions = self.restrictions.as_ref()?;
+        restrictions
+            .speed_limits
+            .iter()
+            .filter(|l| l.when.is_none() && l.at.is_none())
+            .find_map(|l| l.max_speed)
+            .map(|s| s.kmh())
+    }
+
+    /// From an access rule denying one heading for everyone.
+    pub fn one_way(&self) -> Option<OneWay> {
+        let restrictions = self.restrictions.as_ref()?;
+        restrictions.access.iter().find_map(|rule| {
+            let (AccessKind::Denied, condition) = rule.kind()? else {
+                return None;
+            };
+            let when = condition.when.as_ref()?.as_object()?;
+            if when.len() != 1 {
+                return None;
+            }
+            match when.get("heading")?.as_str()? {
+                "backward" => Some(OneWay::Forward),
+                "forward" => Some(OneWay::Backward),
+                _ => None,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn road(json: &str) -> RoadProperties {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn lanes_plain_list() {
+        let road = road(
+            r#"{"class": "primary", "lanes": [{"direction": "forward"}, {"direction": "backward"}]}"#,
+        );
+        assert!(matches!(road.lanes, Some(Ranged::Value(_))));
+        assert_eq!(road.lane_count(), Some(2));
+    }
+
+    #[test]
+    fn lanes_rules() {
+        let road = road(
+            r#"{"class": "primary", "lanes": [
+                {"value": [{"direction": "forward"}, {"direction": "forward"}, {"direction": "backward"}]},
+                {"at": [0.5, 1.0], "value": [{"direction": "forward"}]}
+            ]}"#,
+        );
+        assert!(matches!(road.lanes, Some(Ranged::Rules(_))));
+        assert_eq!(road.lane_count(), Some(3));
+        let ranges: Vec<_> = road
+            .lanes
+            .as_ref()
+            .unwrap()
+            .values()
+            .map(|(at, _)| at)
+            .collect();
+        assert_eq!(ranges, [None, Some([0.5, 1.0])]);
+    }
+
+    #[test]
+    fn surface_plain_and_rules() {
+        assert_eq!(
+            road(r#"{"class": "x", "surface": "paved"}"#).surface(),
+            Some("paved")
+        );
+        let rules = road(
+            r#"{"class": "x", "surface": [{"at": [0, 0.3], "value": "unpaved"}, {"value": "paved"}]}"#,
+        );
+        assert_eq!(rules.surface(), Some("paved"));
+    }
+
+    #[test]
+    fn speed_limits() {
+        let road = road(
+            r#"{"class": "x", "restrictions": {"speedLimits": [
+                {"maxSpeed": [30, "mph"], "at": [0, 0.2]},
+                {"maxSpeed": [20, "km/h"], "when": {"during": "Mo-Fr 07:00-09:00"}},
+                {"maxSpeed": [50, "km/h"]}
+            ]}}"#,
+        );
+        assert_eq!(road.max_speed_kmh(), Some(50.));
+        let limits = &road.restrictions.as_ref().unwrap().speed_limits;
+        assert_eq!(limits[0].max_speed, Some(Speed(30., SpeedUnit::Mph)));
+        assert!((limits[0].max_speed.unwrap().kmh() - 48.28).abs() < 0.01);
+    }
+
+    #[test]
+    fn one_way_from_access() {
+        let road = road(
+            r#"{"class": "x", "restrictions": {"access": [{"denied": {"when": {"heading": "backward"}}}]}}"#,
+        );
+        assert_eq!(road.one_way(), Some(OneWay::Forward));
+    }
+}
@@ -0,0 +1,511 @@
+use bevy::prelude::*;
+use geo::{IsConvex, MinimumRotatedRect};
+use geo_types::{LineString, Polygon};
+use std::str::FromStr;
+
+use crate::building::Building;
+
+// https://docs.overturemaps.org/reference/buildings/building (roof_shape)
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RoofShape {
+    #[default]
+    Flat,
+    Gabled,
+    Hipped,
+    Pyramidal,
+    Skillion,
+    Dome,
+    Onion,
+}
+
+impl FromStr for RoofShape {
+    type Err = ();
+
+    /// The remaining Overture shapes map to the closest one drawn.
+    fn from_str(s: &str) -> Result<RoofShape, ()> {
+        Ok(match s {
+            "flat" => RoofShape::Flat,
+            "gabled" | "saltbox" | "gambrel" | "round" => RoofShape::Gabled,
+            "hipped" | "half_hipped" | "mansard" => RoofShape::Hipped,
+            "pyramidal" => RoofShape::Pyramidal,
+            "skillion" | "sawtooth" => RoofShape::Skillion,
+            "dome" | "spherical" => RoofShape::Dome,
+            "onion" => RoofShape::Onion,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// Roof pitch used when `roof_height` is missing.
+const PITCH_DEG: f64 = 30.;
+const SKILLION_PITCH_DEG: f64 = 15.;
+
+/// Widens the footprint's bounding rectangle so its edges never fall
+/// outside the planes' regions through rounding.
+const EPSILON: f64 = 0.01;
+
+/// `(scale towards the centre, height fraction)` from eave to top.
+const ONION_PROFILE: [(f64, f64); 8] = [
+    (1.0, 0.0),
+    (1.2, 0.15),
+    (1.25, 0.3),
+    (1.1, 0.45),
+    (0.75, 0.6),
+    (0.4, 0.75),
+    (0.15, 0.88),
+    (0.0, 1.0),
+];
+const DOME_STEPS: usize = 8;
+
+/// Plane over the footprint, `y = a * x + b * z + c` in building-local metres.
+type RoofPlane = [f64; 3];
+
+fn plane_at(plane: &RoofPlane, p: [f64; 2]) -> f64 {
+    plane[0] * p[0] + plane[1] * p[1] + plane[2]
+}
+
+/// Roof geometry over a building footprint, from the eave upwards.
+///
+/// Gabled, hipped and skillion roofs are the lower envelope of a few planes
+/// laid over the footprint's minimum rotated rectangle, with the ridge along
+/// its long side or across `roof_direction`. Pyramids, domes and onions are
+/// rings of the footprint scaled towards its centre, which only stay inside
+/// one another on convex footprints: other footprints get a hipped roof for a
+/// pyramid and a flat one for the others.
+#[derive(Debug, Clone)]
+pub struct Roof {
+    pub shape: RoofShape,
+    /// From the eave to the top.
+    pub height: f32,
+    planes: Vec<RoofPlane>,
+    centre: [f64; 2],
+}
+
+impl Roof {
+    /// `max_height` is the whole wall height the roof may take from.
+    pub fn new(building: &Building, max_height: f32) -> Self {
+        let shape: RoofShape = building
+            .attrs
+            .roof_shape
+            .as_deref()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default();
+        // Rings can't go around courtyards, and cross themselves when
+        // scaled on concave footprints.
+        let convex = || {
+            let line: LineString = building.line.iter().map(|p| (p[0], p[1])).collect();
+            building.holes.is_empty() && line.is_convex()
+        };
+        let shape = match shape {
+            RoofShape::Pyramidal if !convex() => RoofShape::Hipped,
+            RoofShape::Dome | RoofShape::Onion if !convex() => RoofShape::Flat,
+            shape => shape,
+        };
+        let flat = Roof {
+            shape: RoofShape::Flat,
+            height: 0.,
+            planes: vec![],
+            centre: [0., 0.],
+        };
+        if shape == RoofShape::Flat || building.line.len() < 4 {
+            return flat;
+        }
+
+        let Some([ridge, across]) = roof_axes(building) else {
+            return flat;
+        };
+        let dot = |p: &[f64; 2], d: [f64; 2]| p[0] * d[0] + p[1] * d[1];
+        let (mut u0, mut u1, mut v0, mut v1) = (f64::MAX, f64::MIN, f64::MAX, f64::MIN);
+        for p in building.line.iter() {
+            let (u, v) = (dot(p, ridge), dot(p, across));
+            (u0, u1) = (u0.min(u), u1.max(u));
+            (v0, v1) = (v0.min(v), v1.max(v));
+        }
+        (u0, u1, v0, v1) = (u0 - EPSILON, u1 + EPSILON, v0 - EPSILON, v1 + EPSILON);
+        // Half the span across the ridge.
+        let w = (v1 - v0) / 2.;
+
+        let default_height = match shape {
+            RoofShape::Skillion => 2. * w * SKILLION_PITCH_DEG.to_radians().tan(),
+            RoofShape::Dome => w,
+            RoofShape::Onion => 1.5 * w,
+            _ => w * PITCH_DEG.to_radians().tan(),
+        };
+        let height = building
+            .attrs
+            .roof_height
+            .unwrap_or(default_height)
+            .clamp(0., max_height as f64);
+
+        // `height * (d . p - o) / span`, zero at offset `o` along `d`.
+        let slope = |d: [f64; 2], o: f64, span: f64| -> RoofPlane {
+            [
+                height * d[0] / span,
+                height * d[1] / span,
+                -height * o / span,
+            ]
+        };
+        let neg = |d: [f64; 2]| [-d[0], -d[1]];
+        let planes: Vec<RoofPlane> = match shape {
+            RoofShape::Gabled => vec![slope(across, v0, w), slope(neg(across), -v1, w)],
+            RoofShape::Hipped => {
+                // Hips at the same pitch as the sides, meeting in a point
+                // on footprints wider than long.
+                let end = w.min((u1 - u0) / 2.);
+                vec![
+                    slope(across, v0, w),
+                    slope(neg(across), -v1, w),
+                    slope(ridge, u0, end),
+                    slope(neg(ridge), -u1, end),
+                ]
+            }
+            // `across` points down the slope.
+            RoofShape::Skillion => vec![slope(neg(across), -v1, v1 - v0)],
+            _ => vec![],
+        };
+
+        let n = (building.line.len() - 1) as f64;
+        let centre = building.line[..building.line.len() - 1]
+            .iter()
+            .fold([0., 0.], |c, p| [c[0] + p[0] / n, c[1] + p[1] / n]);
+
+        Roof {
+            shape,
+            height: height as f32,
+            planes,
+            centre,
+        }
+    }
+
+    /// Height above the eave of the roof over a footprint point, zero along
+    /// the eaves and for the ring shapes.
+    pub fn height_at(&self, p: [f64; 2]) -> f32 {
+        self.planes
+            .iter()
+            .map(|plane| plane_at(plane, p))
+            .min_by(f64::total_cmp)
+            .map_or(0., |h| h.max(0.) as f32)
+    }
+
+    /// `ring` with points added where its edges pass under a ridge or hip,
+    /// so walls can follow the roof up into gables.
+    pub fn split_ring(&self, ring: &[[f64; 2]]) -> Vec<[f64; 2]> {
+        let mut split: Vec<[f64; 2]> = Vec::with_capacity(ring.len());
+        for [p, q] in ring.array_windows::<2>() {
+            split.push(*p);
+            let mut ts: Vec<f64> = vec![];
+            for (i, a) in self.planes.iter().enumerate() {
+                for b in self.planes[i + 1..].iter() {
+                    let (gp, gq) = (
+                        plane_at(a, *p) - plane_at(b, *p),
+                        plane_at(a, *q) - plane_at(b, *q),
+                    );
+                    if gp * gq < 0. {
+                        ts.push(gp / (gp - gq));
+                    }
+                }
+            }
+            ts.sort_by(f64::total_cmp);
+            split.extend(
+                ts.into_iter()
+                    .map(|t| [p[0] + t * (q[0] - p[0]), p[1] + t * (q[1] - p[1])]),
+            );
+        }
+        split.extend(ring.last());
+        split
+    }
+
+    /// Triangles with flat normals, local to the building and the eave.
+    pub fn mesh(&self, building: &Building) -> RoofMesh {
+        let mut mesh = RoofMesh::default();
+        match self.shape {
+            RoofShape::Pyramidal => self.rings(building, &[(1., 0.), (0., 1.)], &mut mesh),
+            RoofShape::Dome => {
+                let profile: Vec<(f64, f64)> = (0..=DOME_STEPS)
+                    .map(|i| {
+                        let a = i as f64 / DOME_STEPS as f64 * std::f64::consts::FRAC_PI_2;
+                        (a.cos(), a.sin())
+                    })
+                    .collect();
+                self.rings(building, &profile, &mut mesh)
+            }
+            RoofShape::Onion => self.rings(building, &ONION_PROFILE, &mut mesh),
+            _ => self.planes(building, &mut mesh),
+        }
+        mesh
+    }
+
+    /// Each footprint triangle cut into the parts under each plane.
+    fn planes(&self, building: &Building, mesh: &mut RoofMesh) {
+        let flat: [RoofPlane; 1] = [[0., 0., 0.]];
+        let planes: &[RoofPlane] = if self.planes.is_empty() {
+            &flat
+        } else {
+            &self.planes
+        };
+        for t in building.triangle_indices.chunks(3) {
+            let triangle: Vec<[f64; 2]> = t
+                .iter()
+                .map(|i| {
+                    let v = building.vertices[*i as usize];
+                    [v[0], v[2]]
+                })
+                .collect();
+            for (i, plane) in planes.iter().enumerate() {
+                let mut part = triangle.clone();
+                for (j, other) in planes.iter().enumerate() {
+                    if i != j {
+                        part = clip(&part, |p| plane_at(plane, p) - plane_at(other, p));
+                    }
+                }
+                let lift =
+                    |p: [f64; 2]| Vec3::new(p[0] as f32, plane_at(plane, p) as f32, p[1] as f32);
+                for k in 1..part.len().saturating_sub(1) {
+                    mesh.triangle(lift(part[0]), lift(part[k]), lift(part[k + 1]), Vec3::Y);
+                }
+            }
+        }
+    }
+
+    /// Footprint rings scaled and raised along `profile`.
+    fn rings(&self, building: &Building, profile: &[(f64, f64)], mesh: &mut RoofMesh) {
+        let c = self.centre;
+        let ring = |(scale, rise): (f64, f64)| -> Vec<Vec3> {
+            building
+                .line
+                .iter()
+                .map(|p| {
+                    Vec3::new(
+                        (c[0] + (p[0] - c[0]) * scale) as f32,
+                        (rise * self.height as f64) as f32,
+                        (c[1] + (p[1] - c[1]) * scale) as f32,
+                    )
+                })
+                .collect()
+        };
+        for [low, high] in profile.array_windows::<2>() {
+            let (low, high) = (ring(*low), ring(*high));
+            for i in 0..low.len() - 1 {
+                let outwards = low[i] - Vec3::new(c[0] as f32, low[i].y, c[1] as f32);
+                mesh.triangle(low[i], low[i + 1], high[i + 1], outwards);
+                mesh.triangle(low[i], high[i + 1], high[i], outwards);
+            }
+        }
+    }
+}
+
+/// Perpendicular unit vectors along and across the ridge.
+fn roof_axes(building: &Building) -> Option<[[f64; 2]; 2]> {
+    let ridge = match building.attrs.roof_direction {
+        // Bearing of the ridge, with north towards -z.
+        Some(bearing) => {
+            let b = bearing.to_radians();
+            [b.sin(), -b.cos()]
+        }
+        // Along the long side of the footprint.
+        None => {
+            let footprint = Polygon::new(
+                LineString::from(
+                    building
+                        .line
+                        .iter()
+                        .map(|p| (p[0], p[1]))
+                        .collect::<Vec<_>>(),
+                ),
+                vec![],
+            );
+            let rect = footprint.minimum_rotated_rect()?;
+            let c: Vec<[f64; 2]> = rect.exterior().coords().map(|c| [c.x, c.y]).collect();
+            let e1 = [c[1][0] - c[0][0], c[1][1] - c[0][1]];
+            let e2 = [c[2][0] - c[1][0], c[2][1] - c[1][1]];
+            if e1[0].hypot(e1[1]) >= e2[0].hypot(e2[1]) {
+                e1
+            } else {
+                e2
+            }
+        }
+    };
+    let len = ridge[0].hypot(ridge[1]);
+    if !len.is_normal() {
+        return None;
+    }
+    let ridge = [ridge[0] / len, ridge[1] / len];
+    Some([ridge, [-ridge[1], ridge[0]]])
+}
+
+/// Part of a convex polygon where `f <= 0`, `f` linear.
+fn clip(polygon: &[[f64; 2]], f: impl Fn([f64; 2]) -> f64) -> Vec<[f64; 2]> {
+    let mut out = vec![];
+    for (i, p) in polygon.iter().enumerate() {
+        let q = polygon[(i + 1) % polygon.len()];
+        let (fp, fq) = (f(*p), f(q));
+        if fp <= 0. {
+            out.push(*p);
+        }
+        if (fp < 0. && fq > 0.) || (fp > 0. && fq < 0.) {
+            let t = fp / (fp - fq);
+            out.push([p[0] + t * (q[0] - p[0]), p[1] + t * (q[1] - p[1])]);
+        }
+    }
+    out
+}
+
+#[derive(Default)]
+pub struct RoofMesh {
+    pub vertices: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+impl RoofMesh {
+    /// Adds `a b c` facing the side of `facing`, skipping slivers.
+    fn triangle(&mut self, a: Vec3, b: Vec3, c: Vec3, facing: Vec3) {
+        let normal = (b - a).cross(c - a);
+        if normal.length_squared() < 1e-8 {
+            return;
+        }
+        // Counter-clockwise seen from the front.
+        let (b, c, normal) = if normal.dot(facing) >= 0. {
+            (b, c, normal)
+        } else {
+            (c, b, -normal)
+        };
+        let normal = normal.normalize().to_array();
+        let offset = self.vertices.len() as u32;
+        self.vertices
+            .extend([a.to_array(), b.to_array(), c.to_array()]);
+        self.normals.extend([normal; 3]);
+        self.indices.extend([offset, offset + 1, offset + 2]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::building::BuildingGeometryProps;
+    use crate::overture::FeatureMeta;
+
+    /// 20 m east by 10 m south, the ridge runs east-west unless directed.
+    const RECT: [[f64; 2]; 4] = [[0., 0.], [20., 0.], [20., 10.], [0., 10.]];
+    const L_SHAPE: [[f64; 2]; 6] = [
+        [0., 0.],
+        [20., 0.],
+        [20., 10.],
+        [10., 10.],
+        [10., 20.],
+        [0., 20.],
+    ];
+
+    fn building(ring: &[[f64; 2]], shape: &str, roof_height: Option<f64>) -> Building {
+        let mut line = ring.to_vec();
+        line.push(ring[0]);
+        let props = BuildingGeometryProps {
+            translate: [0., 0.],
+            height: None,
+            num_floors: None,
+            line,
+            holes: vec![],
+            vertices: vec![],
+            triangle_indices: vec![],
+        };
+        let mut building = Building::from_props(props, None, None, FeatureMeta::default());
+        building.attrs.roof_shape = Some(shape.to_string());
+        building.attrs.roof_height = roof_height;
+        building
+    }
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.02, "{a} != {b}");
+    }
+
+    #[test]
+    fn gabled_ridge_along_the_long_side() {
+        let roof = Roof::new(&building(&RECT, "gabled", Some(3.)), 10.);
+        assert_eq!(roof.shape, RoofShape::Gabled);
+        assert_near(roof.height, 3.);
+        assert_near(roof.height_at([10., 5.]), 3.);
+        // Gable ends go up to the ridge.
+        assert_near(roof.height_at([0., 5.]), 3.);
+        assert_near(roof.height_at([10., 2.5]), 1.5);
+        assert_near(roof.height_at([10., 0.]), 0.);
+        assert_near(roof.height_at([10., 10.]), 0.);
+    }
+
+    #[test]
+    fn gabled_default_pitch() {
+        let roof = Roof::new(&building(&RECT, "gabled", None), 10.);
+        assert_near(roof.height, 5. * (PITCH_DEG.to_radians().tan() as f32));
+    }
+
+    #[test]
+    fn hipped_ends_slope_down() {
+        let roof = Roof::new(&building(&RECT, "hipped", Some(3.)), 10.);
+        assert_near(roof.height_at([10., 5.]), 3.);
+        assert_near(roof.height_at([2.5, 5.]), 1.5);
+        assert_near(roof.height_at([0., 5.]), 0.);
+        assert_near(roof.height_at([20., 5.]), 0.);
+    }
+
+    #[test]
+    fn skillion_slopes_across() {
+        let roof = Roof::new(&building(&RECT, "skillion", Some(3.)), 10.);
+        let (north, south) = (roof.height_at([10., 0.]), roof.height_at([10., 10.]));
+        assert_near(north.max(south), 3.);
+        assert_near(north.min(south), 0.);
+        assert_near(roof.height_at([10., 5.]), 1.5);
+        // One plane, no ridge.
+        assert_near(roof.height_at([0., 0.]), north);
+    }
+
+    #[test]
+    fn roof_direction_turns_the_ridge() {
+        let mut north_south = building(&RECT, "gabled", Some(3.));
+        north_south.attrs.roof_direction = Some(0.);
+        let roof = Roof::new(&north_south, 10.);
+        assert_near(roof.height_at([10., 0.]), 3.);
+        assert_near(roof.height_at([10., 10.]), 3.);
+        assert_near(roof.height_at([0., 5.]), 0.);
+        assert_near(roof.height_at([5., 5.]), 1.5);
+    }
+
+    #[test]
+    fn trims_to_the_walls() {
+        let roof = Roof::new(&building(&RECT, "gabled", Some(50.)), 4.);
+        assert_near(roof.height, 4.);
+        assert_near(roof.height_at([10., 5.]), 4.);
+    }
+
+    #[test]
+    fn no_roof_below_the_eaves() {
+        let roof = Roof::new(&building(&RECT, "gabled", Some(3.)), 10.);
+        assert_eq!(roof.height_at([10., -5.]), 0.);
+        assert_eq!(roof.height_at([10., 15.]), 0.);
+    }
+
+    #[test]
+    fn splits_gable_walls_under_the_ridge() {
+        let roof = Roof::new(&building(&RECT, "gabled", Some(3.)), 10.);
+        let mut ring = RECT.to_vec();
+        ring.push(RECT[0]);
+        let split = roof.split_ring(&ring);
+        assert_eq!(split.len(), ring.len() + 2);
+        let added: Vec<&[f64; 2]> = split.iter().filter(|p| !ring.contains(p)).collect();
+        assert_eq!(added.len(), 2);
+        for p in added {
+            assert!((p[1] - 5.).abs() < 0.02, "{p:?}");
+            assert_near(roof.height_at(*p), 3.);
+        }
+    }
+
+    #[test]
+    fn rings_only_on_convex_footprints() {
+        let shape =
+            |ring: &[[f64; 2]], shape: &str| Roof::new(&building(ring, shape, None), 10.).shape;
+        assert_eq!(shape(&RECT, "pyramidal"), RoofShape::Pyramidal);
+        assert_eq!(shape(&RECT, "dome"), RoofShape::Dome);
+        assert_eq!(shape(&L_SHAPE, "pyramidal"), RoofShape::Hipped);
+        assert_eq!(shape(&L_SHAPE, "dome"), RoofShape::Flat);
+        assert_eq!(shape(&L_SHAPE, "onion"), RoofShape::Flat);
+        assert_eq!(shape(&L_SHAPE, "gabled"), RoofShape::Gabled);
+    }
+}
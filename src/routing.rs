@@ -0,0 +1,518 @@
+use bevy::{pbr::NotShadowCaster, prelude::*, render::mesh::*};
+use bevy_egui::{egui, EguiContexts};
+use bevy_mod_picking::prelude::{Click, Pointer, PointerButton};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::material::MapMaterialHandle;
+use crate::road_graph::{update_road_graph, EdgeId, NodeId, RoadGraph};
+use crate::selection::select_feature;
+use crate::transportation::{RoadClass, RoadSegment};
+
+/// Route overlay width, in metres.
+const ROUTE_WIDTH: f32 = 3.;
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RouteProfile {
+    #[default]
+    Car,
+    Bike,
+    Foot,
+}
+
+impl RouteProfile {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RouteProfile::Car => "car",
+            RouteProfile::Bike => "bike",
+            RouteProfile::Foot => "foot",
+        }
+    }
+
+    /// Typical speed in km/h, `None` where the profile may not go.
+    pub fn speed(&self, road_class: RoadClass) -> Option<f64> {
+        match self {
+            RouteProfile::Car => match road_class {
+                RoadClass::Motorway => Some(110.),
+                RoadClass::Trunk => Some(90.),
+                RoadClass::Primary => Some(60.),
+                RoadClass::Secondary => Some(50.),
+                RoadClass::Tertiary | RoadClass::Unclassified => Some(40.),
+                RoadClass::Residential | RoadClass::Unknown => Some(30.),
+                RoadClass::LivingStreet | RoadClass::ParkingAisle | RoadClass::Driveway => {
+                    Some(10.)
+                }
+                RoadClass::Pedestrian
+                | RoadClass::Footway
+                | RoadClass::Steps
+                | RoadClass::Track
+                | RoadClass::Cycleway
+                | RoadClass::Bridleway => None,
+            },
+            RouteProfile::Bike => match road_class {
+                RoadClass::Motorway | RoadClass::Trunk | RoadClass::Steps => None,
+                RoadClass::Cycleway => Some(20.),
+                RoadClass::Pedestrian | RoadClass::Footway => Some(8.),
+                RoadClass::Track | RoadClass::Bridleway => Some(12.),
+                _ => Some(16.),
+            },
+            RouteProfile::Foot => match road_class {
+                RoadClass::Motorway | RoadClass::Trunk => None,
+                RoadClass::Steps => Some(3.),
+                _ => Some(5.),
+            },
+        }
+    }
+
+    fn max_speed(&self) -> f64 {
+        match self {
+            RouteProfile::Car => 110.,
+            RouteProfile::Bike => 20.,
+            RouteProfile::Foot => 5.,
+        }
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RouteMetric {
+    /// Road length.
+    Shortest,
+    /// Travel time from the profile speed per `RoadClass`.
+    #[default]
+    Fastest,
+}
+
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub edges: Vec<EdgeId>,
+    /// Metres.
+    pub distance: f64,
+    /// Seconds.
+    pub duration: f64,
+    /// World x/z in metres, origin to destination.
+    pub points: Vec<[f64; 2]>,
+}
+
+#[derive(Resource, Default)]
+pub struct RouteState {
+    pub profile: RouteProfile,
+    pub metric: RouteMetric,
+    /// Next clicks set origin and destination.
+    pub picking: bool,
+    pub origin: Option<[f64; 2]>,
+    pub destination: Option<[f64; 2]>,
+    pub route: Option<Route>,
+    pub dirty: bool,
+}
+
+#[derive(Component)]
+pub struct RouteOverlay;
+
+#[derive(Copy, Clone, PartialEq)]
+struct Open {
+    estimate: f64,
+    node: NodeId,
+}
+
+impl Eq for Open {}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Min-heap on the estimate.
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    (b[0] - a[0]).hypot(b[1] - a[1])
+}
+
+/// A* from `origin` to `destination` over the edges the profile allows.
+pub fn find_route(
+    graph: &RoadGraph,
+    origin: NodeId,
+    destination: NodeId,
+    profile: RouteProfile,
+    metric: RouteMetric,
+) -> Option<Route> {
+    let cost = |length: f64, speed: f64| match metric {
+        RouteMetric::Shortest => length,
+        RouteMetric::Fastest => length / (speed / 3.6),
+    };
+    let target = graph.nodes[destination].position;
+    // Straight line at full speed never overestimates.
+    let heuristic = |node: NodeId| {
+        cost(
+            distance(graph.nodes[node].position, target),
+            profile.max_speed(),
+        )
+    };
+
+    let mut best: HashMap<NodeId, f64> = HashMap::from([(origin, 0.)]);
+    let mut came_from: HashMap<NodeId, (NodeId, EdgeId)> = HashMap::new();
+    let mut open = BinaryHeap::from([Open {
+        estimate: heuristic(origin),
+        node: origin,
+    }]);
+
+    while let Some(Open { estimate, node }) = open.pop() {
+        if node == destination {
+            break;
+        }
+        let so_far = best[&node];
+        if estimate > so_far + heuristic(node) {
+            continue; // stale entry
+        }
+        for &edge_id in graph.edges_of(node) {
+            let edge = &graph.edges[edge_id];
+            let Some(speed) = profile.speed(edge.road_class) else {
+                continue;
+            };
+            let next = if edge.from == node {
+                edge.to
+            } else {
+                edge.from
+            };
+            let next_cost = so_far + cost(edge.length, speed);
+            if best.get(&next).map_or(true, |c| next_cost < *c) {
+                best.insert(next, next_cost);
+                came_from.insert(next, (node, edge_id));
+                open.push(Open {
+                    estimate: next_cost + heuristic(next),
+                    node: next,
+                });
+            }
+        }
+    }
+
+    if !best.contains_key(&destination) {
+        return None;
+    }
+    let mut edges: Vec<EdgeId> = vec![];
+    let mut node = destination;
+    while let Some((prev, edge)) = came_from.get(&node) {
+        edges.push(*edge);
+        node = *prev;
+    }
+    edges.reverse();
+
+    let mut route = Route {
+        edges: vec![],
+        distance: 0.,
+        duration: 0.,
+        points: vec![],
+    };
+    let mut node = origin;
+    for edge_id in edges {
+        let edge = &graph.edges[edge_id];
+        route.distance += edge.length;
+        route.duration += edge.length / (profile.speed(edge.road_class).unwrap() / 3.6);
+        if edge.from == node {
+            route.points.extend(edge.points.iter().copied());
+            node = edge.to;
+        } else {
+            route.points.extend(edge.points.iter().rev().copied());
+            node = edge.from;
+        }
+        route.edges.push(edge_id);
+    }
+    // Edges share their end points; RoadSegment can't mesh zero-length steps.
+    route.points.dedup_by(|a, b| distance(*a, *b) < 0.01);
+    Some(route)
+}
+
+/// Closest node with at least one edge the profile may use.
+fn snap(graph: &RoadGraph, position: [f64; 2], profile: RouteProfile) -> Option<NodeId> {
+    graph.nearest_node_by(position, |node| {
+        graph
+            .neighbours(node)
+            .any(|(_, edge)| profile.speed(edge.road_class).is_some())
+    })
+}
+
+/// While picking, primary clicks set the route points. `select_feature`
+/// runs first and ignores them, so they don't also open the selection panel.
+pub fn pick_route_points(mut clicks: EventReader<Pointer<Click>>, mut state: ResMut<RouteState>) {
+    for click in clicks.read() {
+        if !state.picking || click.button != PointerButton::Primary {
+            continue;
+        }
+        let Some(position) = click.hit.position else {
+            continue;
+        };
+        let position = [position.x as f64, position.z as f64];
+        if state.origin.is_none() || state.destination.is_some() {
+            state.origin = Some(position);
+            state.destination = None;
+            state.route = None;
+        } else {
+            state.destination = Some(position);
+            state.picking = false;
+        }
+        state.dirty = true;
+    }
+}
+
+pub fn update_route(
+    mut cmd: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    map_materials: Res<MapMaterialHandle>,
+    graph: Res<RoadGraph>,
+    mut state: ResMut<RouteState>,
+    overlays: Query<Entity, With<RouteOverlay>>,
+) {
+    // Node ids change whenever the graph is rebuilt, so route again.
+    if !state.dirty && !graph.is_changed() {
+        return;
+    }
+    state.dirty = false;
+    for entity in overlays.iter() {
+        cmd.entity(entity).despawn_recursive();
+    }
+    let (Some(origin), Some(destination)) = (state.origin, state.destination) else {
+        state.route = None;
+        return;
+    };
+    let (profile, metric) = (state.profile, state.metric);
+    let now = std::time::Instant::now();
+    state.route = snap(&graph, origin, profile)
+        .zip(snap(&graph, destination, profile))
+        .and_then(|(from, to)| find_route(&graph, from, to, profile, metric));
+    info!("route in {:?}", now.elapsed());
+
+    let Some(route) = &state.route else {
+        return;
+    };
+    if route.points.len() < 2 {
+        return;
+    }
+    let first = route.points[0];
+    let line: Vec<[f64; 2]> = route
+        .points
+        .iter()
+        .map(|p| [p[0] - first[0], p[1] - first[1]])
+        .collect();
+    let segment = RoadSegment::new(&line, ROUTE_WIDTH);
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        VertexAttributeValues::from(segment.vertices),
+    );
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_NORMAL,
+        VertexAttributeValues::from(segment.normals),
+    );
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_UV_0,
+        VertexAttributeValues::from(segment.uvs),
+    );
+    mesh.set_indices(Some(Indices::U32(segment.indices)));
+    cmd.spawn((
+        PbrBundle {
+            mesh: meshes.add(mesh),
+            material: map_materials.route.clone(),
+            transform: Transform::from_xyz(first[0] as f32, 0.3, first[1] as f32),
+            ..default()
+        },
+        RouteOverlay,
+        NotShadowCaster,
+    ));
+}
+
+pub fn route_ui(mut egui: EguiContexts, mut state: ResMut<RouteState>, graph: Res<RoadGraph>) {
+    egui::Window::new("Route")
+        .resizable(false)
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-8., 8.))
+        .show(egui.ctx_mut(), |ui| {
+            let (profile, metric) = (state.profile, state.metric);
+            ui.horizontal(|ui| {
+                for p in [RouteProfile::Car, RouteProfile::Bike, RouteProfile::Foot] {
+                    ui.radio_value(&mut state.profile, p, p.label());
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut state.metric, RouteMetric::Fastest, "fastest");
+                ui.radio_value(&mut state.metric, RouteMetric::Shortest, "shortest");
+            });
+            if (profile, metric) != (state.profile, state.metric) {
+                state.dirty = true;
+            }
+
+            ui.horizontal(|ui| {
+                if ui.selectable_label(state.picking, "pick points").clicked() {
+                    state.picking = !state.picking;
+                    if state.picking {
+                        state.origin = None;
+                        state.destination = None;
+                        state.dirty = true;
+                    }
+                }
+                if ui.button("clear").clicked() {
+                    state.picking = false;
+                    state.origin = None;
+                    state.destination = None;
+                    state.dirty = true;
+                }
+            });
+
+            if state.picking {
+                ui.label(if state.origin.is_none() {
+                    "click the origin"
+                } else {
+                    "click the destination"
+                });
+            }
+            match (&state.route, state.destination) {
+                (Some(route), _) => {
+                    ui.label(format!("distance: {:.2} km", route.distance / 1000.));
+                    ui.label(format!("time: {:.0} min", (route.duration / 60.).ceil()));
+                }
+                (None, Some(_)) => {
+                    ui.colored_label(egui::Color32::LIGHT_RED, "no route");
+                }
+                (None, None) => {}
+            }
+            ui.weak(format!(
+                "graph: {} nodes, {} edges",
+                graph.nodes.len(),
+                graph.edges.len()
+            ));
+        });
+}
+
+pub struct RoutingPlugin;
+
+impl Plugin for RoutingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RouteState>().add_systems(
+            Update,
+            (
+                pick_route_points.after(select_feature),
+                update_route,
+                route_ui,
+            )
+                .chain()
+                .after(update_road_graph),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overture::FeatureMeta;
+    use crate::road_properties::RoadProperties;
+    use crate::transportation::{Segment, SegmentConnector};
+
+    fn segment(
+        id: &str,
+        from: (&str, [f64; 2]),
+        to: (&str, [f64; 2]),
+        class: RoadClass,
+    ) -> Segment {
+        Segment {
+            meta: FeatureMeta {
+                id: id.to_string(),
+                sources: vec![],
+                update_time: None,
+            },
+            translate: [0., 0.],
+            line: vec![from.1, to.1],
+            road_class: class,
+            road: RoadProperties {
+                class: format!("{class:?}"),
+                names: None,
+                surface: None,
+                flags: None,
+                lanes: None,
+                restrictions: None,
+            },
+            width: None,
+            level: None,
+            connectors: vec![
+                SegmentConnector {
+                    id: from.0.to_string(),
+                    at: Some(0.),
+                },
+                SegmentConnector {
+                    id: to.0.to_string(),
+                    at: Some(1.),
+                },
+            ],
+            ramp_levels: [None, None],
+        }
+    }
+
+    /// `a` to `c` along a motorway through `b` or a residential diagonal,
+    /// and a footway from `a` to `d`.
+    fn graph() -> RoadGraph {
+        let a = ("a", [0., 0.]);
+        let b = ("b", [100., 0.]);
+        let c = ("c", [100., 100.]);
+        let d = ("d", [0., 100.]);
+        let segments = [
+            segment("ab", a, b, RoadClass::Motorway),
+            segment("bc", b, c, RoadClass::Motorway),
+            segment("ac", a, c, RoadClass::Residential),
+            segment("ad", a, d, RoadClass::Footway),
+        ];
+        RoadGraph::build(segments.iter())
+    }
+
+    fn route(
+        graph: &RoadGraph,
+        from: &str,
+        to: &str,
+        profile: RouteProfile,
+        metric: RouteMetric,
+    ) -> Option<Route> {
+        let from = graph.node_by_id(from).unwrap();
+        let to = graph.node_by_id(to).unwrap();
+        find_route(graph, from, to, profile, metric)
+    }
+
+    fn segment_ids(graph: &RoadGraph, route: &Route) -> Vec<String> {
+        route
+            .edges
+            .iter()
+            .map(|e| graph.edges[*e].segment_id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn fastest_takes_the_motorway() {
+        let graph = graph();
+        let route = route(&graph, "a", "c", RouteProfile::Car, RouteMetric::Fastest).unwrap();
+        assert_eq!(segment_ids(&graph, &route), ["ab", "bc"]);
+        assert!((route.distance - 200.).abs() < 1e-9);
+        assert_eq!(route.points.first(), Some(&[0., 0.]));
+        assert_eq!(route.points.last(), Some(&[100., 100.]));
+    }
+
+    #[test]
+    fn shortest_takes_the_diagonal() {
+        let graph = graph();
+        let route = route(&graph, "a", "c", RouteProfile::Car, RouteMetric::Shortest).unwrap();
+        assert_eq!(segment_ids(&graph, &route), ["ac"]);
+        assert!((route.distance - 100. * 2f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn foot_avoids_the_motorway() {
+        let graph = graph();
+        let route = route(&graph, "c", "d", RouteProfile::Foot, RouteMetric::Fastest).unwrap();
+        assert_eq!(segment_ids(&graph, &route), ["ac", "ad"]);
+    }
+
+    #[test]
+    fn car_cannot_reach_footway_only_node() {
+        let graph = graph();
+        assert!(route(&graph, "a", "d", RouteProfile::Car, RouteMetric::Fastest).is_none());
+    }
+}
@@ -0,0 +1,278 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use bevy_mod_picking::prelude::{Click, ListenerInput, Pointer, PointerButton};
+
+use crate::building::Building;
+use crate::overture::FeatureMeta;
+use crate::road_properties::{OneWay, RoadProperties, Speed};
+use crate::routing::RouteState;
+use crate::transportation::RoadSegment;
+
+/// Sent by the `On<Pointer<Click>>` listener on each spawned road and
+/// building mesh.
+#[derive(Event)]
+pub struct FeatureClicked {
+    pub entity: Entity,
+    pub button: PointerButton,
+}
+
+impl From<ListenerInput<Pointer<Click>>> for FeatureClicked {
+    fn from(event: ListenerInput<Pointer<Click>>) -> Self {
+        FeatureClicked {
+            entity: event.listener(),
+            button: event.button,
+        }
+    }
+}
+
+/// Road or building shown in the side panel.
+#[derive(Resource, Default)]
+pub struct Selected(pub Option<Entity>);
+
+/// Primary clicks select, except while they set route points, see
+/// `pick_route_points`, which runs after this.
+pub fn select_feature(
+    mut clicks: EventReader<FeatureClicked>,
+    mut selected: ResMut<Selected>,
+    route: Option<Res<RouteState>>,
+) {
+    let picking = route.is_some_and(|r| r.picking);
+    for click in clicks.read() {
+        if click.button == PointerButton::Primary && !picking {
+            selected.0 = Some(click.entity);
+        }
+    }
+}
+
+/// Name, class and speed limit, shared by the tooltip and the panel.
+pub fn road_summary(road: &RoadProperties) -> Vec<String> {
+    let mut lines = vec![];
+    if let Some(name) = road.names.as_ref().and_then(|n| n.common_local()) {
+        lines.push(name.to_string());
+    }
+    lines.push(road.class.clone());
+    if let Some(surface) = road.surface() {
+        lines.push(format!("surface: {surface}"));
+    }
+    if let Some(speed) = road.max_speed_kmh() {
+        lines.push(format!("max speed: {speed:.0} km/h"));
+    }
+    lines
+}
+
+pub fn selection_panel(
+    mut egui: EguiContexts,
+    mut selected: ResMut<Selected>,
+    roads: Query<&RoadSegment>,
+    buildings: Query<&Building>,
+) {
+    let Some(entity) = selected.0 else {
+        return;
+    };
+    // Its tile may have been unloaded since.
+    let (heading, segment, building) = match (roads.get(entity), buildings.get(entity)) {
+        (Ok(segment), _) => ("Road", Some(segment), None),
+        (_, Ok(building)) => ("Building", None, Some(building)),
+        _ => {
+            selected.0 = None;
+            return;
+        }
+    };
+
+    let mut open = true;
+    egui::SidePanel::left("selection_panel")
+        .resizable(true)
+        .show(egui.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.heading(heading);
+                if ui.small_button("✖").clicked() {
+                    open = false;
+                }
+            });
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("selection_attributes")
+                    .num_columns(2)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        let mut row = |key: &str, value: String| {
+                            ui.label(key);
+                            ui.label(value);
+                            ui.end_row();
+                        };
+                        if let Some(segment) = segment {
+                            road_rows(&mut row, segment);
+                        }
+                        if let Some(building) = building {
+                            building_rows(&mut row, building);
+                        }
+                    });
+            });
+        });
+    if !open {
+        selected.0 = None;
+    }
+}
+
+fn meta_rows(row: &mut impl FnMut(&str, String), meta: &FeatureMeta) {
+    row("id", meta.id.clone());
+    for source in meta.sources.iter() {
+        let mut value = source.dataset.clone();
+        if let Some(record_id) = &source.record_id {
+            value += &format!(" {record_id}");
+        }
+        row("source", value);
+    }
+    if let Some(update_time) = &meta.update_time {
+        row("updated", update_time.clone());
+    }
+    if let Some(confidence) = meta.confidence() {
+        row("confidence", format!("{confidence:.2}"));
+    }
+}
+
+fn building_rows(row: &mut impl FnMut(&str, String), building: &Building) {
+    meta_rows(row, &building.meta);
+    if let Some(names) = &building.names {
+        for name in names.common.iter() {
+            row("name", format!("{} ({})", name.value, name.language));
+        }
+    }
+    if let Some(class) = building.class {
+        row("class", format!("{class:?}").to_lowercase());
+    }
+    if let Some(height) = building.height {
+        row("height", format!("{height:.1} m"));
+    }
+    if let Some(num_floors) = building.num_floors {
+        row("floors", num_floors.to_string());
+    }
+    let (min_height, height) = building.extrusion();
+    row(
+        "extruded",
+        format!(
+            "{min_height:.1}-{height:.1} m ({})",
+            building.height_source.label()
+        ),
+    );
+    let attrs = &building.attrs;
+    let optional = [
+        ("min height", attrs.min_height.map(|h| format!("{h:.1} m"))),
+        ("min floor", attrs.min_floor.map(|f| f.to_string())),
+        ("roof shape", attrs.roof_shape.clone()),
+        (
+            "roof height",
+            attrs.roof_height.map(|h| format!("{h:.1} m")),
+        ),
+        (
+            "roof direction",
+            attrs.roof_direction.map(|d| format!("{d:.0}°")),
+        ),
+        ("facade color", attrs.facade_color.clone()),
+        ("facade material", attrs.facade_material.clone()),
+        ("roof color", attrs.roof_color.clone()),
+        ("roof material", attrs.roof_material.clone()),
+        ("building id", attrs.building_id.clone()),
+    ];
+    for (key, value) in optional {
+        if let Some(value) = value {
+            row(key, value);
+        }
+    }
+    for flag in building.qa.iter() {
+        row("qa", flag.label().to_string());
+    }
+}
+
+fn road_rows(row: &mut impl FnMut(&str, String), segment: &RoadSegment) {
+    if let Some(meta) = &segment.meta {
+        meta_rows(row, meta);
+    }
+    row("length", format!("{:.1} m", segment.length()));
+    row(
+        "width",
+        format!(
+            "{:.1} m ({})",
+            segment.width,
+            segment.width_source.map_or("?", |s| s.label())
+        ),
+    );
+    if let Some(level) = segment.level {
+        row("level", level.to_string());
+    }
+    let Some(road) = &segment.road else {
+        return;
+    };
+    row("class", road.class.clone());
+    if let Some(names) = &road.names {
+        for name in names.common.iter() {
+            row("name", format!("{} ({})", name.value, name.language));
+        }
+    }
+    if let Some(surface) = &road.surface {
+        for (at, value) in surface.values() {
+            row(&at_key("surface", at), value.clone());
+        }
+    }
+    if let Some(flags) = &road.flags {
+        for (at, value) in flags.values() {
+            row(&at_key("flags", at), value.join(", "));
+        }
+    }
+    if let Some(lanes) = &road.lanes {
+        for (at, value) in lanes.values() {
+            let directions: Vec<&str> = value
+                .iter()
+                .map(|l| l.direction.as_deref().unwrap_or("?"))
+                .collect();
+            row(&at_key("lanes", at), directions.join(", "));
+        }
+    }
+    if let Some(one_way) = road.one_way() {
+        row(
+            "one-way",
+            match one_way {
+                OneWay::Forward => "forward",
+                OneWay::Backward => "backward",
+            }
+            .to_string(),
+        );
+    }
+    let Some(restrictions) = &road.restrictions else {
+        return;
+    };
+    for limit in restrictions.speed_limits.iter() {
+        let speed =
+            |s: Option<Speed>| s.map_or("-".to_string(), |s| format!("{:.0} km/h", s.kmh()));
+        let mut value = format!("{} .. {}", speed(limit.min_speed), speed(limit.max_speed));
+        if let Some(when) = &limit.when {
+            value += &format!(" when {when}");
+        }
+        row(&at_key("speed limit", limit.at), value);
+    }
+    for rule in restrictions.access.iter() {
+        if let Some((kind, condition)) = rule.kind() {
+            let mut value = format!("{kind:?}").to_lowercase();
+            if let Some(when) = &condition.when {
+                value += &format!(" when {when}");
+            }
+            row(&at_key("access", rule.at), value);
+        }
+    }
+}
+
+fn at_key(key: &str, at: Option<[f64; 2]>) -> String {
+    match at {
+        Some([from, to]) => format!("{key} @{from:.2}-{to:.2}"),
+        None => key.to_string(),
+    }
+}
+
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<FeatureClicked>()
+            .init_resource::<Selected>()
+            .add_systems(Update, (select_feature, selection_panel).chain());
+    }
+}
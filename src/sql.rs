@@ -0,0 +1,15 @@
+/// `s` as a single-quoted SQL string literal, e.g. a path for `read_parquet`.
+pub fn sql_string(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_quotes() {
+        assert_eq!(sql_string("parquet/a.parquet"), "'parquet/a.parquet'");
+        assert_eq!(sql_string("it's"), "'it''s'");
+    }
+}
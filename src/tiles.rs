@@ -0,0 +1,489 @@
+use bevy::prelude::*;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy::utils::{HashMap, HashSet};
+use bevy_panorbit_camera::PanOrbitCamera;
+
+use crate::building::{spawn_building, Building};
+use crate::error::LoadReport;
+use crate::ground::{spawn_ground_area, GroundArea};
+use crate::height::HeightEstimator;
+use crate::loading::{MapLoadParams, SPAWN_BATCH};
+use crate::material::{ColorMaterials, MapMaterialHandle};
+use crate::place::{spawn_place, Place, PlaceMarkerMesh};
+use crate::projection::Projection;
+use crate::query_base::BaseQueryParams;
+use crate::transportation::{spawn_transportation, ConnectorLevels, RoadSegment, Segment};
+
+/// Square tile of the map in the `Projection` world frame, `x` east and `z` south.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileCoord {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl TileCoord {
+    pub fn from_world(pos: Vec3, tile_size: f64) -> Self {
+        TileCoord {
+            x: (pos.x as f64 / tile_size).floor() as i32,
+            z: (pos.z as f64 / tile_size).floor() as i32,
+        }
+    }
+
+    pub fn distance(&self, other: &TileCoord) -> i32 {
+        (self.x - other.x).abs().max((self.z - other.z).abs())
+    }
+
+    /// `[lon_min, lat_min, lon_max, lat_max]` of the tile.
+    /// Longitudes are taken along `z = 0` and latitudes along `x = 0`, so
+    /// tiles share borders and never overlap even where the projection's
+    /// grid isn't aligned with meridians.
+    pub fn lon_lat_bounds(&self, tile_size: f64, projection: Projection) -> [f64; 4] {
+        let x0 = self.x as f64 * tile_size;
+        let z0 = self.z as f64 * tile_size;
+        let lon = |x: f64| projection.inverse([x, 0.])[0];
+        let lat = |z: f64| projection.inverse([0., z])[1];
+        [lon(x0), lat(z0 + tile_size), lon(x0 + tile_size), lat(z0)]
+    }
+}
+
+#[derive(Resource, Debug)]
+pub struct TileConfig {
+    /// Tile edge in metres.
+    pub tile_size: f64,
+    /// Tiles within this many tiles of the camera focus are loaded.
+    pub load_radius: i32,
+    /// Tiles further than this are despawned; kept above `load_radius` to avoid thrashing on borders.
+    pub unload_radius: i32,
+}
+
+impl Default for TileConfig {
+    fn default() -> Self {
+        TileConfig {
+            tile_size: 500.,
+            load_radius: 2,
+            unload_radius: 3,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TileData {
+    pub buildings: Vec<Building>,
+    pub segments: Vec<Segment>,
+    pub places: Vec<Place>,
+    pub ground: Vec<GroundArea>,
+    pub report: LoadReport,
+    /// Queries that failed as a whole, the tile stays empty for that layer.
+    pub errors: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct MapTile {
+    pub task: Option<Task<TileData>>,
+    pub buildings: Vec<Building>,
+    pub segments: Vec<Segment>,
+    pub places: Vec<Place>,
+    pub ground: Vec<GroundArea>,
+    /// Ground areas of this tile another loaded tile already shows, see
+    /// `share_ground`.
+    pub ground_shared: Vec<GroundArea>,
+    /// Outlines covered by loaded parts, see `hide_outlines`.
+    pub outlines_hidden: Vec<Building>,
+    pub report: LoadReport,
+    pub errors: Vec<String>,
+    pub buildings_spawned: usize,
+    pub segments_spawned: usize,
+    pub places_spawned: usize,
+    pub ground_spawned: usize,
+}
+
+impl MapTile {
+    pub fn features(&self) -> usize {
+        self.buildings.len() + self.segments.len() + self.places.len() + self.ground.len()
+    }
+
+    pub fn features_spawned(&self) -> usize {
+        self.buildings_spawned + self.segments_spawned + self.places_spawned + self.ground_spawned
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.task.is_none() && self.features_spawned() == self.features()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct MapTiles {
+    pub tiles: HashMap<TileCoord, MapTile>,
+    /// `building_id`s of the loaded building parts. The outlines with these
+    /// ids only cover their parts up and are not shown.
+    pub with_parts: HashSet<String>,
+}
+
+impl MapTiles {
+    pub fn is_ready(&self) -> bool {
+        self.tiles.values().all(MapTile::is_ready)
+    }
+}
+
+fn load_tile(coord: TileCoord, params: &MapLoadParams, tile_size: f64) -> Task<TileData> {
+    let buildings = params.buildings.clone().map(|mut p| {
+        p.bbox = Some(coord.lon_lat_bounds(tile_size, p.projection));
+        p
+    });
+    let building_parts = params.building_parts.clone().map(|mut p| {
+        p.bbox = Some(coord.lon_lat_bounds(tile_size, p.projection));
+        p
+    });
+    let transportation = params.transportation.clone().map(|mut p| {
+        p.bbox = Some(coord.lon_lat_bounds(tile_size, p.projection));
+        p
+    });
+
+    let places = params.places.clone().map(|mut p| {
+        p.bbox = Some(coord.lon_lat_bounds(tile_size, p.projection));
+        p
+    });
+    let base: Vec<BaseQueryParams> = params
+        .base
+        .iter()
+        .cloned()
+        .map(|mut p| {
+            p.bbox = Some(coord.lon_lat_bounds(tile_size, p.projection));
+            p
+        })
+        .collect();
+    let source = params.source.clone();
+
+    AsyncComputeTaskPool::get().spawn(async move {
+        let now = std::time::Instant::now();
+        let mut data = TileData::default();
+        match transportation.map(|p| source.segments(p)).transpose() {
+            Ok(Some((segments, report))) => {
+                data.segments = segments;
+                data.report.merge(report);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!("tile {coord:?} transportation: {e}");
+                data.errors.push(format!("transportation: {e}"));
+            }
+        }
+        match buildings.map(|p| source.buildings(p)).transpose() {
+            Ok(Some((buildings, report))) => {
+                data.buildings = buildings;
+                data.report.merge(report);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!("tile {coord:?} buildings: {e}");
+                data.errors.push(format!("buildings: {e}"));
+            }
+        }
+        match building_parts.map(|p| source.building_parts(p)).transpose() {
+            Ok(Some((parts, report))) => {
+                // Outlines covering these are hidden by `poll_tiles`.
+                data.buildings.extend(parts);
+                data.report.merge(report);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!("tile {coord:?} building parts: {e}");
+                data.errors.push(format!("building parts: {e}"));
+            }
+        }
+        match places.map(|p| source.places(p)).transpose() {
+            Ok(Some((places, report))) => {
+                data.places = places;
+                data.report.merge(report);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!("tile {coord:?} places: {e}");
+                data.errors.push(format!("places: {e}"));
+            }
+        }
+        for p in base {
+            let base_type = p.base_type;
+            match source.base(p) {
+                Ok((ground, report)) => {
+                    data.ground.extend(ground);
+                    data.report.merge(report);
+                }
+                Err(e) => {
+                    error!("tile {coord:?} {base_type:?}: {e}");
+                    data.errors.push(format!("{base_type:?}: {e}"));
+                }
+            }
+        }
+        debug!(
+            "tile {coord:?}: buildings:{} segments:{} places:{} ground:{} skipped:{} in {:?}",
+            data.buildings.len(),
+            data.segments.len(),
+            data.places.len(),
+            data.ground.len(),
+            data.report.skipped.len(),
+            now.elapsed()
+        );
+        data
+    })
+}
+
+pub fn update_tiles(
+    mut cmd: Commands,
+    config: Res<TileConfig>,
+    params: Res<MapLoadParams>,
+    mut map_tiles: ResMut<MapTiles>,
+    cameras: Query<&PanOrbitCamera>,
+    tiled: Query<(Entity, &TileCoord)>,
+) {
+    let Some(camera) = cameras.iter().next() else {
+        return;
+    };
+    let focus = TileCoord::from_world(camera.focus, config.tile_size);
+
+    for x in -config.load_radius..=config.load_radius {
+        for z in -config.load_radius..=config.load_radius {
+            let coord = TileCoord {
+                x: focus.x + x,
+                z: focus.z + z,
+            };
+            map_tiles.tiles.entry(coord).or_insert_with(|| MapTile {
+                task: Some(load_tile(coord, &params, config.tile_size)),
+                ..default()
+            });
+        }
+    }
+
+    // Dropping a tile also drops (cancels) its pending query task.
+    let count = map_tiles.tiles.len();
+    map_tiles
+        .tiles
+        .retain(|coord, _| coord.distance(&focus) <= config.unload_radius);
+    if map_tiles.tiles.len() < count {
+        share_ground(&mut map_tiles);
+        hide_outlines(&mut map_tiles);
+    }
+    for (entity, coord) in tiled.iter() {
+        if !map_tiles.tiles.contains_key(coord) {
+            cmd.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// `base` features are loaded by every tile they touch. Each is shown by one
+/// loaded tile at a time: the shared areas whose id no tile shows yet become
+/// pending ones of their tile, so an area dropped with an unloaded tile is
+/// shown again by another tile holding it.
+fn share_ground(map_tiles: &mut MapTiles) {
+    let mut shown: HashSet<String> = map_tiles
+        .tiles
+        .values()
+        .flat_map(|t| t.ground.iter().map(|a| a.id.clone()))
+        .collect();
+    for tile in map_tiles.tiles.values_mut() {
+        let (show, shared): (Vec<_>, Vec<_>) = std::mem::take(&mut tile.ground_shared)
+            .into_iter()
+            .partition(|a| !shown.contains(&a.id));
+        shown.extend(show.iter().map(|a| a.id.clone()));
+        tile.ground.extend(show);
+        tile.ground_shared = shared;
+    }
+}
+
+/// Sets the ramps of all loaded segments from the connectors of all loaded
+/// tiles. Spawned segments whose ramps changed go back to the pending ones,
+/// their ids are returned for despawning.
+fn resolve_ramps(map_tiles: &mut MapTiles) -> HashSet<String> {
+    let levels = ConnectorLevels::new(map_tiles.tiles.values().flat_map(|t| t.segments.iter()));
+    let mut respawn: HashSet<String> = HashSet::new();
+    for tile in map_tiles.tiles.values_mut() {
+        let mut changed: Vec<bool> = vec![];
+        for segment in tile.segments.iter_mut() {
+            let ramp_levels = levels.ramp_levels(segment);
+            changed.push(ramp_levels != segment.ramp_levels);
+            segment.ramp_levels = ramp_levels;
+        }
+        let spawned = tile.segments_spawned;
+        if !changed[..spawned].contains(&true) {
+            continue;
+        }
+        let pending = tile.segments.split_off(spawned);
+        let (keep, redo): (Vec<_>, Vec<_>) = std::mem::take(&mut tile.segments)
+            .into_iter()
+            .zip(changed)
+            .partition(|(_, changed)| !changed);
+        respawn.extend(redo.iter().map(|(s, _)| s.meta.id.clone()));
+        tile.segments_spawned = keep.len();
+        tile.segments = keep.into_iter().chain(redo).map(|(s, _)| s).collect();
+        tile.segments.extend(pending);
+    }
+    respawn
+}
+
+/// Collects the `building_id`s of all loaded parts into `with_parts` and moves
+/// the outlines they cover to `outlines_hidden`, spawned ones are despawned by
+/// the caller. Hidden outlines whose parts were all unloaded go back to the
+/// pending buildings.
+fn hide_outlines(map_tiles: &mut MapTiles) {
+    map_tiles.with_parts = map_tiles
+        .tiles
+        .values()
+        .flat_map(|t| t.buildings.iter())
+        .filter_map(|b| b.attrs.building_id.clone())
+        .collect();
+    let with_parts = &map_tiles.with_parts;
+    for tile in map_tiles.tiles.values_mut() {
+        let mut pending = tile.buildings.split_off(tile.buildings_spawned);
+        pending.append(&mut tile.outlines_hidden);
+        let (spawned, mut hidden): (Vec<_>, Vec<_>) = std::mem::take(&mut tile.buildings)
+            .into_iter()
+            .partition(|b| !with_parts.contains(&b.meta.id));
+        let (pending, hide): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|b| !with_parts.contains(&b.meta.id));
+        hidden.extend(hide);
+        tile.outlines_hidden = hidden;
+        tile.buildings_spawned = spawned.len();
+        tile.buildings = spawned;
+        tile.buildings.extend(pending);
+    }
+}
+
+/// Takes the finished tile queries. Ground areas, heights, ramps and part
+/// outlines depend on neighbour tiles, so each load redoes them over all
+/// loaded tiles: ground areas already shown by a neighbour are held back,
+/// missing heights of the buildings not spawned yet are estimated again,
+/// segments whose ramps changed are respawned and outlines covered by parts,
+/// often in a neighbour tile of their outline, are hidden.
+pub fn poll_tiles(
+    mut cmd: Commands,
+    heights: Res<HeightEstimator>,
+    mut map_tiles: ResMut<MapTiles>,
+    buildings: Query<(Entity, &Building)>,
+    roads: Query<(Entity, &RoadSegment)>,
+) {
+    let mut loaded = false;
+    for tile in map_tiles.tiles.values_mut() {
+        if tile.task.as_ref().is_some_and(|t| t.is_finished()) {
+            let data = block_on(tile.task.take().unwrap());
+            tile.buildings = data.buildings;
+            tile.segments = data.segments;
+            tile.places = data.places;
+            tile.ground_shared = data.ground;
+            tile.report = data.report;
+            tile.errors = data.errors;
+            loaded = true;
+        }
+    }
+    if !loaded {
+        return;
+    }
+
+    let map_tiles = &mut *map_tiles;
+    share_ground(map_tiles);
+
+    let index = heights.index(map_tiles.tiles.values().flat_map(|t| t.buildings.iter()));
+    for tile in map_tiles.tiles.values_mut() {
+        let start = tile.buildings_spawned;
+        heights.estimate(&index, &mut tile.buildings[start..]);
+    }
+
+    let respawn = resolve_ramps(map_tiles);
+    for (entity, road) in roads.iter() {
+        if road.meta.as_ref().is_some_and(|m| respawn.contains(&m.id)) {
+            cmd.entity(entity).despawn_recursive();
+        }
+    }
+
+    hide_outlines(map_tiles);
+    for (entity, building) in buildings.iter() {
+        if map_tiles.with_parts.contains(&building.meta.id) {
+            cmd.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Meshes and spawns up to `SPAWN_BATCH` features per frame across all loaded
+/// tiles, nearest to the camera focus first: ground areas, roads, buildings
+/// and places, in that order within a tile.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_tiles(
+    mut cmd: Commands,
+    config: Res<TileConfig>,
+    cameras: Query<&PanOrbitCamera>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    map_materials: Res<MapMaterialHandle>,
+    mut color_materials: ResMut<ColorMaterials>,
+    place_marker: Res<PlaceMarkerMesh>,
+    mut map_tiles: ResMut<MapTiles>,
+) {
+    let focus = cameras.iter().next().map_or_else(TileCoord::default, |c| {
+        TileCoord::from_world(c.focus, config.tile_size)
+    });
+    let mut coords: Vec<TileCoord> = map_tiles
+        .tiles
+        .iter()
+        .filter(|(_, tile)| tile.task.is_none() && !tile.is_ready())
+        .map(|(coord, _)| *coord)
+        .collect();
+    coords.sort_by_key(|c| (c.x - focus.x).pow(2) + (c.z - focus.z).pow(2));
+
+    let mut budget = SPAWN_BATCH;
+    for coord in coords.iter() {
+        if budget == 0 {
+            break;
+        }
+        let tile = map_tiles.tiles.get_mut(coord).unwrap();
+
+        let start = tile.ground_spawned;
+        let end = (start + budget).min(tile.ground.len());
+        for area in tile.ground[start..end].iter() {
+            let entity = spawn_ground_area(&mut cmd, &mut meshes, area, &map_materials);
+            cmd.entity(entity).insert(*coord);
+        }
+        tile.ground_spawned = end;
+        budget -= end - start;
+
+        let start = tile.segments_spawned;
+        let end = (start + budget).min(tile.segments.len());
+        for segment in tile.segments[start..end].iter() {
+            let entity = spawn_transportation(
+                &mut cmd,
+                &mut meshes,
+                &mut materials,
+                segment,
+                &map_materials,
+            );
+            cmd.entity(entity).insert(*coord);
+        }
+        tile.segments_spawned = end;
+        budget -= end - start;
+
+        let start = tile.buildings_spawned;
+        let end = (start + budget).min(tile.buildings.len());
+        for building in tile.buildings[start..end].iter() {
+            for entity in spawn_building(
+                &mut cmd,
+                &mut meshes,
+                &mut materials,
+                building,
+                &map_materials,
+                &mut color_materials,
+            ) {
+                cmd.entity(entity).insert(*coord);
+            }
+        }
+        tile.buildings_spawned = end;
+        budget -= end - start;
+
+        let start = tile.places_spawned;
+        let end = (start + budget).min(tile.places.len());
+        for place in tile.places[start..end].iter() {
+            let entity = spawn_place(&mut cmd, &place_marker, place, &map_materials);
+            cmd.entity(entity).insert(*coord);
+        }
+        tile.places_spawned = end;
+        budget -= end - start;
+    }
+}
@@ -1,16 +1,18 @@
 use bevy::{pbr::NotShadowCaster, prelude::*, render::mesh::*};
+use bevy_mod_picking::prelude::{Click, On, Pointer};
 use geo_types::LineString;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::f32::consts::{FRAC_PI_2, FRAC_PI_3, FRAC_PI_6, PI};
 use std::ops::Sub;
 use strum_macros::EnumIter;
 
-use crate::{KxyGeodesic, MapMaterialHandle};
+use crate::overture::FeatureMeta;
+use crate::projection::Projection;
+use crate::road_properties::RoadProperties;
+use crate::selection::FeatureClicked;
+use crate::MapMaterialHandle;
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Road {
-    pub class: String,
-}
 #[derive(EnumIter, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum RoadClass {
     // highway=motorway > trunk > primary > secondary > ... > living streets > ... > footway
@@ -32,7 +34,19 @@ pub enum RoadClass {
     Bridleway,    // - bridleway # similar as track but has implied access only for horses
     Unknown,      // - unknown
 }
+/// Metres above the ground plane of level 0 roads, over `BaseType::elevation`.
+const ROAD_ELEVATION: f32 = 0.01;
+/// Metres between road classes on one level.
+const ROAD_LAYER_STEP: f32 = 0.005;
+
 impl RoadClass {
+    /// Height of the ribbon above its level: classes are stacked in
+    /// `depth_bias` order so where ribbons overlap at a junction the higher
+    /// class covers the lower one, whatever their widths.
+    pub fn elevation(&self) -> f32 {
+        ROAD_ELEVATION + self.depth_bias() * ROAD_LAYER_STEP
+    }
+
     pub fn depth_bias(&self) -> f32 {
         match self {
             RoadClass::Motorway => 16.,
@@ -78,37 +92,197 @@ impl RoadClass {
     }
 }
 
+/// A `connectors` entry: older releases list bare connector ids, newer ones
+/// add the linear-referencing position along the segment.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ConnectorRef {
+    Id(String),
+    At {
+        connector_id: String,
+        at: Option<f64>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct SegmentConnector {
+    pub id: String,
+    /// 0..=1 along the segment, `None` when the data doesn't say.
+    pub at: Option<f64>,
+}
+
+impl From<ConnectorRef> for SegmentConnector {
+    fn from(value: ConnectorRef) -> Self {
+        match value {
+            ConnectorRef::Id(id) => SegmentConnector { id, at: None },
+            ConnectorRef::At { connector_id, at } => SegmentConnector {
+                id: connector_id,
+                at,
+            },
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Segment {
+    pub meta: FeatureMeta,
     pub translate: [f64; 2],
+    /// Relative to `translate`, in metres.
     pub line: Vec<[f64; 2]>,
-    pub k: KxyGeodesic,
     pub road_class: RoadClass,
+    pub road: RoadProperties,
+    /// Metres, from the data.
     pub width: Option<f32>,
+    pub level: Option<i32>,
+    pub connectors: Vec<SegmentConnector>,
+    /// Level of the segments joined at the start and end where it differs
+    /// from `level`, see `ConnectorLevels::ramp_levels`.
+    pub ramp_levels: [Option<i32>; 2],
 }
 
-#[derive(Resource, Debug)]
-pub struct SegmentsRes {
-    pub segments: Vec<Segment>,
+impl Segment {
+    /// Length in metres.
+    pub fn length(&self) -> f64 {
+        self.line
+            .array_windows()
+            .map(|[a, b]| (b[0] - a[0]).hypot(b[1] - a[1]))
+            .sum()
+    }
+
+    fn end_connectors(&self) -> [Option<&str>; 2] {
+        if self.connectors.len() < 2 {
+            return [None, None];
+        }
+        [
+            self.connectors.first().map(|c| c.id.as_str()),
+            self.connectors.last().map(|c| c.id.as_str()),
+        ]
+    }
+
+    /// Data width, else lanes × `LANE_WIDTH`, else the `RoadClass` default.
+    pub fn road_width(&self) -> (f32, WidthSource) {
+        if let Some(width) = self.width.filter(|w| *w > 0.) {
+            (width, WidthSource::Measured)
+        } else if let Some(lanes) = self.road.lane_count().filter(|l| *l > 0) {
+            (lanes as f32 * LANE_WIDTH, WidthSource::Lanes)
+        } else {
+            (RoadWidth::from(&self.road_class), WidthSource::Class)
+        }
+    }
+
+    pub fn is_tunnel(&self) -> bool {
+        self.level.is_some_and(|l| l < 0) || self.road.is_tunnel()
+    }
+
+    /// Height of each `line` point: raised levels sit `LEVEL_HEIGHT` apart,
+    /// tunnels stay on the ground, ramps blend towards joined levels.
+    pub fn heights(&self) -> Vec<f32> {
+        let length = self.length();
+        let ramp = RAMP_LENGTH.min(length / 2.);
+        let own = level_height(self.level);
+        let start = self.ramp_levels[0].map(|l| level_height(Some(l)));
+        let end = self.ramp_levels[1].map(|l| level_height(Some(l)));
+        let mut walked = 0.;
+        let mut prev = self.line[0];
+        self.line
+            .iter()
+            .map(|p| {
+                walked += (p[0] - prev[0]).hypot(p[1] - prev[1]);
+                prev = *p;
+                let mut h = own;
+                if let Some(start) = start.filter(|_| walked < ramp) {
+                    h = start + (own - start) * (walked / ramp) as f32;
+                }
+                if let Some(end) = end.filter(|_| length - walked < ramp) {
+                    h = end + (own - end) * ((length - walked) / ramp) as f32;
+                }
+                h
+            })
+            .collect()
+    }
 }
+
+/// Metres per lane when only the lane count is known.
+pub const LANE_WIDTH: f32 = 3.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WidthSource {
+    /// `width` column.
+    Measured,
+    /// Lane count × `LANE_WIDTH`.
+    Lanes,
+    /// `RoadWidth` per `RoadClass`.
+    Class,
+}
+
+impl WidthSource {
+    pub fn label(&self) -> &'static str {
+        match self {
+            WidthSource::Measured => "measured",
+            WidthSource::Lanes => "from lanes",
+            WidthSource::Class => "class default",
+        }
+    }
+}
+
+/// Metres between road levels.
+pub const LEVEL_HEIGHT: f32 = 6.;
+/// Metres over which a segment climbs to a joined segment's level.
+const RAMP_LENGTH: f64 = 40.;
+
+fn level_height(level: Option<i32>) -> f32 {
+    level.unwrap_or(0).max(0) as f32 * LEVEL_HEIGHT
+}
+
+/// Levels of the segments ending at each connector, over all loaded tiles
+/// since a ramp's joined segment is often in a neighbour tile.
+#[derive(Debug, Default)]
+pub struct ConnectorLevels(HashMap<String, Vec<i32>>);
+
+impl ConnectorLevels {
+    pub fn new<'a>(segments: impl IntoIterator<Item = &'a Segment>) -> Self {
+        let mut levels: HashMap<String, Vec<i32>> = HashMap::new();
+        for segment in segments {
+            for connector in segment.end_connectors().into_iter().flatten() {
+                levels
+                    .entry(connector.to_string())
+                    .or_default()
+                    .push(segment.level.unwrap_or(0));
+            }
+        }
+        ConnectorLevels(levels)
+    }
+
+    /// `ramp_levels` of `segment`: only the segment further from the ground
+    /// ramps, towards the lowest level it joins, so both meet at the connector.
+    pub fn ramp_levels(&self, segment: &Segment) -> [Option<i32>; 2] {
+        let own = segment.level.unwrap_or(0);
+        segment.end_connectors().map(|connector| {
+            self.0
+                .get(connector?)?
+                .iter()
+                .copied()
+                .filter(|l| l.abs() < own.abs())
+                .min_by_key(|l| l.abs())
+        })
+    }
+}
+
 pub fn line_string_road(
     line_string: LineString,
-    k: KxyGeodesic,
-    center: [f64; 2],
+    projection: Projection,
 ) -> ([f64; 2], Vec<[f64; 2]>) {
     let c1 = line_string
         .coords()
         .nth(0)
         .expect("To take exterior:0 coordinate");
-    let first_point_xz: [f64; 2] = [c1.x * k[0] - center[0], -c1.y * k[1] - center[1]]; // Yto-Z
+    let first_point_xz: [f64; 2] = projection.forward(c1.x, c1.y);
 
     let line: Vec<[f64; 2]> = line_string
         .coords()
         .map(|c| {
-            [
-                c.x * k[0] - center[0] - first_point_xz[0],
-                -c.y * k[1] - center[1] - first_point_xz[1], // Yto-Z
-            ]
+            let [x, z] = projection.forward(c.x, c.y);
+            [x - first_point_xz[0], z - first_point_xz[1]]
         })
         .collect();
     (first_point_xz, line)
@@ -131,18 +305,6 @@ pub fn line_string_road(
 //     (k, first_point_position)
 // }
 
-pub fn transportations_start(
-    mut cmd: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    transportations_res: Res<SegmentsRes>,
-    map_materials: Res<MapMaterialHandle>,
-) {
-    for item in transportations_res.segments.iter() {
-        spawn_transportation(&mut cmd, &mut meshes, &mut materials, item, &map_materials);
-    }
-}
-
 impl From<&RoadClass> for Color {
     fn from(value: &RoadClass) -> Self {
         match value {
@@ -198,12 +360,15 @@ pub fn spawn_transportation(
     _materials: &mut ResMut<Assets<StandardMaterial>>,
     transportation: &Segment,
     map_materials: &Res<MapMaterialHandle>,
-) {
-    let width = transportation
-        .width
-        .unwrap_or_else(|| RoadWidth::from(&transportation.road_class));
-
-    let segment = RoadSegment::new(&transportation.line, width);
+) -> Entity {
+    let (width, width_source) = transportation.road_width();
+
+    let mut segment =
+        RoadSegment::with_heights(&transportation.line, &transportation.heights(), width);
+    segment.meta = Some(transportation.meta.clone());
+    segment.road = Some(transportation.road.clone());
+    segment.level = transportation.level;
+    segment.width_source = Some(width_source);
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
     mesh.insert_attribute(
         Mesh::ATTRIBUTE_POSITION,
@@ -221,24 +386,29 @@ pub fn spawn_transportation(
 
     let translate: Vec3 = Vec3::new(
         transportation.translate[0] as f32,
-        width * 0.01,
+        transportation.road_class.elevation(),
         transportation.translate[1] as f32,
     );
     let transform = Transform::from_translation(translate);
     cmd.spawn((
         PbrBundle {
             mesh: meshes.add(mesh),
-            material: map_materials
-                .road
-                .get(&transportation.road_class)
-                .unwrap()
-                .clone(),
+            material: if transportation.is_tunnel() {
+                &map_materials.road_tunnel
+            } else {
+                &map_materials.road
+            }
+            .get(&transportation.road_class)
+            .unwrap()
+            .clone(),
             transform,
             ..Default::default()
         },
         segment,
         NotShadowCaster,
-    ));
+        On::<Pointer<Click>>::send_event::<FeatureClicked>(),
+    ))
+    .id()
 }
 
 #[derive(Component, Debug)]
@@ -249,6 +419,14 @@ pub struct RoadSegment {
     pub vertices: Vec<[f32; 3]>,
     pub normals: Vec<[f32; 3]>,
     pub uvs: Vec<[f32; 2]>,
+    /// Set for transportation segments.
+    pub meta: Option<FeatureMeta>,
+    /// Set for transportation segments, not for other ribbons.
+    pub road: Option<RoadProperties>,
+    pub level: Option<i32>,
+    /// Metres.
+    pub width: f32,
+    pub width_source: Option<WidthSource>,
 }
 
 impl RoadSegment {
@@ -260,18 +438,39 @@ impl RoadSegment {
             vertices: vec![],
             normals: vec![],
             uvs: vec![],
+            meta: None,
+            road: None,
+            level: None,
+            width: 0.,
+            width_source: None,
         }
     }
 
+    /// Metres along the points, ramps included.
+    pub fn length(&self) -> f32 {
+        self.points
+            .array_windows()
+            .map(|[a, b]| a.distance(*b))
+            .sum()
+    }
+
     pub fn new(line: &[[f64; 2]], width: f32) -> Self {
+        Self::with_heights(line, &vec![0.; line.len()], width)
+    }
+
+    /// Ribbon following `line` with a height per point; stays flat across.
+    pub fn with_heights(line: &[[f64; 2]], heights: &[f32], width: f32) -> Self {
         let half_width: f32 = width / 2.;
         let mut segm = Self::empty();
+        segm.width = width;
         segm.points = line
             .iter()
-            .map(|pos| Vec3::new(pos[0] as f32, 0., pos[1] as f32))
+            .zip(heights)
+            .map(|(pos, h)| Vec3::new(pos[0] as f32, *h, pos[1] as f32))
             .collect::<Vec<Vec3>>();
+        let flat = |v: Vec3| Vec3::new(v.x, 0., v.z);
 
-        let first_angle = segm.points[1].sub(segm.points[0]).normalize();
+        let first_angle = flat(segm.points[1].sub(segm.points[0])).normalize();
         let first_left = Quat::from_rotation_y(FRAC_PI_2).mul_vec3(first_angle);
         let first_right = Quat::from_rotation_y(-FRAC_PI_2).mul_vec3(first_angle);
         let first_left_behind = Quat::from_rotation_y(FRAC_PI_2 + FRAC_PI_3).mul_vec3(first_angle);
@@ -298,8 +497,8 @@ impl RoadSegment {
         segm.uvs.push([0.1, 1.]);
 
         for [prev, this, next] in segm.points.array_windows().copied() {
-            let prev_angle = this.sub(prev).normalize();
-            let next_angle = next.sub(this).normalize();
+            let prev_angle = flat(this.sub(prev)).normalize();
+            let next_angle = flat(next.sub(this)).normalize();
             let angle = (prev_angle + next_angle).normalize();
 
             let left = Quat::from_rotation_y(FRAC_PI_2).mul_vec3(angle);
@@ -315,9 +514,9 @@ impl RoadSegment {
             segm.uvs.push([0.5, 1.]);
         }
 
-        let last_angle = segm.points[segm.points.len() - 1]
-            .sub(segm.points[segm.points.len() - 2])
-            .normalize();
+        let last_angle =
+            flat(segm.points[segm.points.len() - 1].sub(segm.points[segm.points.len() - 2]))
+                .normalize();
         let last_left = Quat::from_rotation_y(FRAC_PI_2).mul_vec3(last_angle);
         let last_right = Quat::from_rotation_y(-FRAC_PI_2).mul_vec3(last_angle);
         let last_left_after = Quat::from_rotation_y(FRAC_PI_2 - FRAC_PI_3).mul_vec3(last_angle);